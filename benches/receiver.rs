@@ -0,0 +1,76 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+use std::time::SystemTime;
+use taptap::gateway::link::{Address, Receiver, Sink, Type};
+use taptap::gateway::{Frame, FrameRef, GatewayID};
+
+/// Polling traffic from a bus with a few dozen gateways: receive requests and their responses.
+fn traffic() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for id in 0x1201..0x1241 {
+        let gateway = GatewayID::try_from(id).unwrap();
+        let request = Frame {
+            address: Address::To(gateway),
+            frame_type: Type::RECEIVE_REQUEST,
+            payload: vec![0x00, 0x01, 0x18, 0x83, 0x04],
+        };
+        let response = Frame {
+            address: Address::From(gateway),
+            frame_type: Type::RECEIVE_RESPONSE,
+            payload: (0..=200u8).collect(),
+        };
+        bytes.extend_from_slice(&request.encode());
+        bytes.extend_from_slice(&response.encode());
+    }
+    bytes
+}
+
+/// A sink which looks at each frame without keeping it.
+#[derive(Default)]
+struct Count(usize);
+
+impl Sink for Count {
+    fn frame(&mut self, frame: FrameRef<'_>) {
+        self.0 += frame.payload.len();
+    }
+}
+
+fn receiver(c: &mut Criterion) {
+    let bytes = traffic();
+    let mut group = c.benchmark_group("receiver");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    group.bench_function("borrowed", |b| {
+        let mut rx = Receiver::new(Count::default());
+        b.iter(|| rx.extend_from_slice(black_box(&bytes)));
+    });
+
+    // The state machine stepped for every byte, as the receiver used to decode
+    group.bench_function("borrowed per byte", |b| {
+        let mut rx = Receiver::new(Count::default());
+        b.iter(|| rx.extend_from_slice_per_byte(black_box(&bytes), SystemTime::UNIX_EPOCH));
+    });
+
+    group.bench_function("owned", |b| {
+        let mut rx = Receiver::new(Vec::<Frame>::new());
+        b.iter(|| {
+            rx.extend_from_slice(black_box(&bytes));
+            rx.sink_mut().clear();
+        });
+    });
+
+    // Serial ports tend to deliver a few bytes per read
+    group.bench_function("borrowed in small reads", |b| {
+        let mut rx = Receiver::new(Count::default());
+        b.iter(|| {
+            for chunk in black_box(&bytes).chunks(16) {
+                rx.extend_from_slice(chunk);
+            }
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, receiver);
+criterion_main!(benches);
@@ -164,19 +164,19 @@ mod tests {
             super::crc(pv::LongAddress([
                 0x04, 0xC0, 0x5B, 0x40, 0x00, 0x9A, 0x57, 0xA2
             ])),
-            'L' as u8
+            b'L'
         );
         assert_eq!(
             super::crc(pv::LongAddress([
                 0x04, 0xC0, 0x5B, 0x40, 0x00, 0x79, 0xAC, 0x16
             ])),
-            'V' as u8
+            b'V'
         );
         assert_eq!(
             super::crc(pv::LongAddress([
                 0x04, 0xC0, 0x5B, 0x40, 0x00, 0x79, 0xAB, 0x99
             ])),
-            'W' as u8
+            b'W'
         );
     }
 
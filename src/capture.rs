@@ -1,75 +1,410 @@
-use std::io::ErrorKind::UnexpectedEof;
-use std::io::{BufReader, Read, Write};
+//! Capture files, recording the bytes received from a source along with when they arrived.
+//!
+//! A capture is a gzip stream of records, identified by the gzip header comment. Version 1
+//! captures hold a length and a millisecond timestamp per record. Version 2 captures start with a
+//! JSON `Metadata` header, and their records have microsecond timestamps and an optional
+//! `Direction`. `Writer` writes version 2, and `Reader` reads both.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind::{InvalidData, InvalidInput, UnexpectedEof};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::mem::size_of;
 use std::ops::Add;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use zerocopy::{big_endian, FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
-const GZIP_HEADER_COMMENT: &[u8] = b"taptap capture";
+const GZIP_HEADER_COMMENT_V1: &[u8] = b"taptap capture";
+const GZIP_HEADER_COMMENT_V2: &[u8] = b"taptap capture v2";
+
+/// The largest metadata header a reader accepts, to fail cleanly on corrupt files.
+const MAX_METADATA_LENGTH: u32 = 64 * 1024;
+
+/// Information about a capture, stored at the start of version 2 files.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// A description of the source which was captured, e.g. `tcp 192.0.2.1:502`.
+    pub source: String,
+    /// The name of the host which made the capture.
+    pub host: String,
+    /// The version of `taptap` which made the capture.
+    pub taptap_version: String,
+    /// When the capture was started.
+    pub start_time: DateTime<Utc>,
+}
+
+impl Metadata {
+    /// Describe a capture of `source` starting now, on this host, by this version of `taptap`.
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            host: hostname(),
+            taptap_version: env!("CARGO_PKG_VERSION").into(),
+            start_time: Utc::now(),
+        }
+    }
+}
+
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buffer = [0u8; 256];
+        if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } == 0
+        {
+            if let Ok(name) = std::ffi::CStr::from_bytes_until_nul(&buffer) {
+                return name.to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default()
+}
+
+/// Which way the bytes of a record travelled, when the source knows.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Received from the bus.
+    Rx,
+    /// Transmitted onto the bus.
+    Tx,
+}
+
+/// A slice of bytes from a capture.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Record {
+    pub data: Vec<u8>,
+    /// When the bytes were received, or transmitted.
+    pub timestamp: SystemTime,
+    /// Which way the bytes travelled, if known. Always `None` in version 1 captures.
+    pub direction: Option<Direction>,
+}
 
 #[derive(Debug)]
-pub struct Reader<R: Read>(BufReader<flate2::bufread::GzDecoder<BufReader<R>>>);
+pub struct Reader<R: Read> {
+    inner: BufReader<flate2::bufread::GzDecoder<BufReader<R>>>,
+    metadata: Option<Metadata>,
+}
 
 impl<R: Read> Reader<R> {
+    /// Start reading a capture, reading its header.
+    ///
+    /// Fails with `InvalidData` if `reader` isn't a gzip stream, or is one which isn't a capture.
     pub fn new(reader: R) -> std::io::Result<Self> {
         let gz = flate2::bufread::GzDecoder::new(BufReader::new(reader));
-        if let Some(h) = gz.header() {
-            if h.comment() != Some(GZIP_HEADER_COMMENT) {
-                // warn?
+        let mut inner = BufReader::new(gz);
+        if inner.get_ref().header().is_none() {
+            // Reading surfaces the error which prevented parsing the header
+            inner.fill_buf().map_err(|e| match e.kind() {
+                UnexpectedEof | InvalidInput => {
+                    std::io::Error::new(InvalidData, format!("not a taptap capture file: {}", e))
+                }
+                _ => e,
+            })?;
+        }
+
+        let comment = inner
+            .get_ref()
+            .header()
+            .ok_or_else(|| std::io::Error::new(InvalidData, "not a taptap capture file"))?
+            .comment()
+            .map(<[u8]>::to_vec);
+
+        let metadata = match comment.as_deref() {
+            Some(GZIP_HEADER_COMMENT_V1) => None,
+            Some(GZIP_HEADER_COMMENT_V2) => Some(read_metadata(&mut inner)?),
+            _ => {
+                return Err(std::io::Error::new(
+                    InvalidData,
+                    "not a taptap capture file",
+                ))
             }
+        };
+
+        Ok(Self { inner, metadata })
+    }
+
+    /// The capture's format version.
+    pub fn version(&self) -> u8 {
+        if self.metadata.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Information about the capture, if it is a version 2 capture.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    fn next_v1(&mut self) -> std::io::Result<Option<Record>> {
+        let mut header = [0u8; size_of::<RecordHeaderV1>()];
+        if !read_header(&mut self.inner, &mut header)? {
+            return Ok(None);
         }
 
-        Ok(Self(BufReader::new(gz)))
+        let header = RecordHeaderV1::ref_from_bytes(&header).unwrap(); // infallible
+        let mut data = vec![0; header.data_length.get() as usize];
+        self.inner.read_exact(&mut data)?;
+        Ok(Some(Record {
+            data,
+            timestamp: header.timestamp(),
+            direction: None,
+        }))
+    }
+
+    fn next_v2(&mut self) -> std::io::Result<Option<Record>> {
+        let mut header = [0u8; size_of::<RecordHeaderV2>()];
+        if !read_header(&mut self.inner, &mut header)? {
+            return Ok(None);
+        }
+
+        let header = RecordHeaderV2::ref_from_bytes(&header).unwrap(); // infallible
+        let direction = header.direction()?;
+        let mut data = vec![0; header.data_length.get() as usize];
+        self.inner.read_exact(&mut data)?;
+        Ok(Some(Record {
+            data,
+            timestamp: header.timestamp(),
+            direction,
+        }))
+    }
+}
+
+/// Read the length-prefixed JSON metadata at the start of a version 2 capture.
+fn read_metadata<R: Read>(reader: &mut R) -> std::io::Result<Metadata> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length);
+    if length > MAX_METADATA_LENGTH {
+        return Err(std::io::Error::new(
+            InvalidData,
+            format!("capture metadata too long ({} bytes)", length),
+        ));
+    }
+
+    let mut json = vec![0; length as usize];
+    reader.read_exact(&mut json)?;
+    serde_json::from_slice(&json).map_err(|e| std::io::Error::new(InvalidData, e))
+}
+
+/// Read a record header, returning `false` at the end of the capture.
+fn read_header<R: Read>(reader: &mut R, header: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(header) {
+        Err(e) if e.kind() == UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+        Ok(()) => Ok(true),
     }
 }
 
 impl<R: Read> Iterator for Reader<R> {
-    type Item = std::io::Result<(Vec<u8>, SystemTime)>;
+    type Item = std::io::Result<Record>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut record = [0u8; size_of::<Record>()];
-        match self.0.read_exact(&mut record) {
-            Err(e) if e.kind() == UnexpectedEof => {
-                return None;
-            }
-            Err(e) => return Some(Err(e)),
-            Ok(_) => {}
+        let record = if self.metadata.is_some() {
+            self.next_v2()
+        } else {
+            self.next_v1()
         };
+        record.transpose()
+    }
+}
 
-        let record = Record::ref_from_bytes(&record).unwrap(); // infallible
-        let mut data = vec![0; record.data_length.get() as usize];
-        Some(match self.0.read_exact(&mut data) {
-            Err(e) => Err(e),
-            Ok(_) => Ok((data, record.timestamp())),
-        })
+/// Replays the records of a capture, optionally pacing them according to their timestamps.
+#[derive(Debug)]
+pub struct Replay<R: Read> {
+    reader: Reader<R>,
+    speed: f64,
+    start: Option<(SystemTime, Instant)>,
+}
+
+impl<R: Read> Replay<R> {
+    /// Replay records from `reader`.
+    ///
+    /// A `speed` of `1.0` reproduces the recorded gaps between records in real time, `2.0` replays
+    /// twice as fast, and so on. A `speed` of `0.0` replays records as fast as possible.
+    pub fn new(reader: Reader<R>, speed: f64) -> Self {
+        Self {
+            reader,
+            speed,
+            start: None,
+        }
+    }
+
+    fn pace(&mut self, timestamp: SystemTime) {
+        if self.speed.is_nan() || self.speed <= 0.0 {
+            return;
+        }
+
+        // Pace relative to the first record, so that sleep inaccuracies don't accumulate
+        let (first_timestamp, first_instant) =
+            *self.start.get_or_insert((timestamp, Instant::now()));
+        let offset = timestamp
+            .duration_since(first_timestamp)
+            .unwrap_or_default()
+            .div_f64(self.speed);
+
+        if let Some(delay) = (first_instant + offset).checked_duration_since(Instant::now()) {
+            std::thread::sleep(delay);
+        }
     }
 }
 
+impl<R: Read> Iterator for Replay<R> {
+    type Item = std::io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.reader.next()?;
+        if let Ok(record) = &item {
+            self.pace(record.timestamp);
+        }
+        Some(item)
+    }
+}
+
+/// Interleaves the records of several captures in timestamp order.
+///
+/// Records with equal timestamps are taken from the earlier reader first. Each capture is expected
+/// to be in timestamp order already, as written by `taptap record`.
+#[derive(Debug)]
+pub struct Merge<R: Read> {
+    readers: Vec<(Reader<R>, Option<Record>)>,
+}
+
+impl<R: Read> Merge<R> {
+    pub fn new(readers: impl IntoIterator<Item = Reader<R>>) -> Self {
+        Self {
+            readers: readers.into_iter().map(|reader| (reader, None)).collect(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Merge<R> {
+    type Item = std::io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Look at the next record of each reader
+        for (reader, next) in &mut self.readers {
+            if next.is_none() {
+                match reader.next() {
+                    Some(Ok(record)) => *next = Some(record),
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => {}
+                }
+            }
+        }
+
+        // Forget readers which have ended
+        self.readers.retain(|(_, next)| next.is_some());
+
+        let (_, next) = self
+            .readers
+            .iter_mut()
+            .min_by_key(|(_, next)| next.as_ref().map(|record| record.timestamp))?;
+        next.take().map(Ok)
+    }
+}
+
+/// Statistics about the content of a capture.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Summary {
+    /// The number of records.
+    pub records: u64,
+    /// The number of bytes in all records.
+    pub bytes: u64,
+    /// The timestamp of the earliest record.
+    pub first: Option<SystemTime>,
+    /// The timestamp of the latest record.
+    pub last: Option<SystemTime>,
+    /// The link layer `Receiver`'s counters after decoding all records.
+    pub counters: crate::gateway::link::Counters,
+}
+
+impl Summary {
+    /// Read all records from `reader`, counting them and decoding their link layer frames.
+    pub fn of<R: Read>(reader: Reader<R>) -> std::io::Result<Self> {
+        let mut summary = Self::default();
+        let mut rx = crate::gateway::link::Receiver::new(());
+        for record in reader {
+            let record = record?;
+            summary.records += 1;
+            summary.bytes += record.data.len() as u64;
+            summary.first = Some(
+                summary
+                    .first
+                    .map_or(record.timestamp, |t| t.min(record.timestamp)),
+            );
+            summary.last = Some(
+                summary
+                    .last
+                    .map_or(record.timestamp, |t| t.max(record.timestamp)),
+            );
+            rx.extend_from_slice(&record.data);
+        }
+        summary.counters = *rx.counters();
+        Ok(summary)
+    }
+
+    /// The time between the first and last records.
+    pub fn duration(&self) -> Duration {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => last.duration_since(first).unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Writes version 2 captures.
 #[derive(Debug)]
 pub struct Writer<W: Write>(flate2::write::GzEncoder<W>);
 
 impl<W: Write> Writer<W> {
-    pub fn new(writer: W) -> std::io::Result<Self> {
-        let gz = flate2::GzBuilder::new()
-            .comment(GZIP_HEADER_COMMENT)
+    pub fn new(writer: W, metadata: &Metadata) -> std::io::Result<Self> {
+        let mut gz = flate2::GzBuilder::new()
+            .comment(GZIP_HEADER_COMMENT_V2)
             .write(writer, flate2::Compression::best());
+
+        let json = serde_json::to_vec(metadata)?;
+        gz.write_all(&(json.len() as u32).to_be_bytes())?;
+        gz.write_all(&json)?;
+
         Ok(Self(gz))
     }
 
-    pub fn write(&mut self, mut bytes: &[u8], timestamp: SystemTime) -> std::io::Result<()> {
+    /// Write bytes received at `timestamp`, of unknown direction.
+    pub fn write(&mut self, bytes: &[u8], timestamp: SystemTime) -> std::io::Result<()> {
+        self.write_directed(bytes, timestamp, None)
+    }
+
+    pub fn write_record(&mut self, record: &Record) -> std::io::Result<()> {
+        self.write_directed(&record.data, record.timestamp, record.direction)
+    }
+
+    pub fn write_directed(
+        &mut self,
+        mut bytes: &[u8],
+        timestamp: SystemTime,
+        direction: Option<Direction>,
+    ) -> std::io::Result<()> {
         while bytes.len() > u16::MAX as usize {
             let (left, right) = bytes.split_at(u16::MAX as usize);
-            self.write(left, timestamp)?;
+            self.write_directed(left, timestamp, direction)?;
             bytes = right;
         }
 
         assert!(bytes.len() <= u16::MAX as usize);
 
-        let mut buffer = vec![0u8; bytes.len() + size_of::<Record>()];
-        let (record, data) = buffer.as_mut_slice().split_at_mut(size_of::<Record>());
-        let record = Record::mut_from_bytes(record).unwrap();
-        record.set_timestamp(timestamp);
-        record.data_length.set(bytes.len() as u16);
+        let mut buffer = vec![0u8; bytes.len() + size_of::<RecordHeaderV2>()];
+        let (header, data) = buffer
+            .as_mut_slice()
+            .split_at_mut(size_of::<RecordHeaderV2>());
+        let header = RecordHeaderV2::mut_from_bytes(header).unwrap();
+        header.set_direction(direction);
+        header.set_timestamp(timestamp);
+        header.data_length.set(bytes.len() as u16);
         data.copy_from_slice(bytes);
 
         self.0.write_all(&buffer)
@@ -79,6 +414,11 @@ impl<W: Write> Writer<W> {
         self.0.flush()
     }
 
+    /// Access the underlying writer, which receives the compressed output.
+    pub fn get_ref(&self) -> &W {
+        self.0.get_ref()
+    }
+
     pub fn finish(self) -> std::io::Result<W> {
         self.0.finish()
     }
@@ -88,20 +428,236 @@ impl<W: Write> Writer<W> {
     Debug, Copy, Clone, Eq, PartialEq, FromBytes, IntoBytes, Unaligned, KnownLayout, Immutable,
 )]
 #[repr(C)]
-struct Record {
+struct RecordHeaderV1 {
     /// Number of data bytes in this block
     pub data_length: big_endian::U16,
     // Milliseconds since epoch
     pub timestamp: big_endian::U64,
 }
 
-impl Record {
+impl RecordHeaderV1 {
     pub fn timestamp(&self) -> SystemTime {
         UNIX_EPOCH.add(Duration::from_millis(self.timestamp.get()))
     }
+}
+
+#[derive(
+    Debug, Copy, Clone, Eq, PartialEq, FromBytes, IntoBytes, Unaligned, KnownLayout, Immutable,
+)]
+#[repr(C)]
+struct RecordHeaderV2 {
+    /// 0 for unknown, 1 for received, 2 for transmitted
+    pub direction: u8,
+    /// Number of data bytes in this block
+    pub data_length: big_endian::U16,
+    // Microseconds since epoch
+    pub timestamp: big_endian::U64,
+}
+
+impl RecordHeaderV2 {
+    pub fn direction(&self) -> std::io::Result<Option<Direction>> {
+        match self.direction {
+            0 => Ok(None),
+            1 => Ok(Some(Direction::Rx)),
+            2 => Ok(Some(Direction::Tx)),
+            other => Err(std::io::Error::new(
+                InvalidData,
+                format!("invalid record direction {}", other),
+            )),
+        }
+    }
+
+    pub fn set_direction(&mut self, direction: Option<Direction>) {
+        self.direction = match direction {
+            None => 0,
+            Some(Direction::Rx) => 1,
+            Some(Direction::Tx) => 2,
+        };
+    }
+
+    pub fn timestamp(&self) -> SystemTime {
+        UNIX_EPOCH.add(Duration::from_micros(self.timestamp.get()))
+    }
 
     pub fn set_timestamp(&mut self, timestamp: SystemTime) {
         self.timestamp
-            .set(timestamp.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64)
+            .set(timestamp.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            source: "tcp 192.0.2.1:502".into(),
+            host: "cca".into(),
+            taptap_version: "0.2.0".into(),
+            start_time: DateTime::from_timestamp(1723500000, 0).unwrap(),
+        }
+    }
+
+    fn capture(records: &[(&[u8], SystemTime)]) -> Vec<u8> {
+        let mut writer = Writer::new(Vec::new(), &metadata()).unwrap();
+        for (bytes, timestamp) in records {
+            writer.write(bytes, *timestamp).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn record(data: &[u8], timestamp: SystemTime) -> Record {
+        Record {
+            data: data.to_vec(),
+            timestamp,
+            direction: None,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let t = UNIX_EPOCH + Duration::from_micros(1723500000123456);
+        let file = capture(&[
+            (b"\x00\xff\x7e\x07", t),
+            (b"", t),
+            (b"abc", t + Duration::from_secs(1)),
+        ]);
+
+        let reader = Reader::new(file.as_slice()).unwrap();
+        assert_eq!(reader.version(), 2);
+        assert_eq!(reader.metadata(), Some(&metadata()));
+        let records = reader.collect::<std::io::Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            records,
+            vec![
+                record(b"\x00\xff\x7e\x07", t),
+                record(b"", t),
+                record(b"abc", t + Duration::from_secs(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn direction() {
+        let t = UNIX_EPOCH + Duration::from_secs(1723500000);
+        let mut writer = Writer::new(Vec::new(), &metadata()).unwrap();
+        writer
+            .write_directed(b"\x7e\x07", t, Some(Direction::Tx))
+            .unwrap();
+        writer
+            .write_directed(b"\x7e\x08", t, Some(Direction::Rx))
+            .unwrap();
+        let file = writer.finish().unwrap();
+
+        let directions = Reader::new(file.as_slice())
+            .unwrap()
+            .map(|record| record.unwrap().direction)
+            .collect::<Vec<_>>();
+        assert_eq!(directions, vec![Some(Direction::Tx), Some(Direction::Rx)]);
+    }
+
+    #[test]
+    fn version_1() {
+        // A capture written by taptap 0.2
+        let t = UNIX_EPOCH + Duration::from_millis(1723500000123);
+        let mut gz = flate2::GzBuilder::new()
+            .comment(GZIP_HEADER_COMMENT_V1)
+            .write(Vec::new(), flate2::Compression::best());
+        for (bytes, timestamp) in [(&b"\x00\xff"[..], t), (b"abc", t + Duration::from_secs(1))] {
+            let mut header = RecordHeaderV1 {
+                data_length: (bytes.len() as u16).into(),
+                timestamp: 0.into(),
+            };
+            header
+                .timestamp
+                .set(timestamp.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64);
+            gz.write_all(header.as_bytes()).unwrap();
+            gz.write_all(bytes).unwrap();
+        }
+        let file = gz.finish().unwrap();
+
+        let reader = Reader::new(file.as_slice()).unwrap();
+        assert_eq!(reader.version(), 1);
+        assert_eq!(reader.metadata(), None);
+        let records = reader.collect::<std::io::Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            records,
+            vec![
+                record(b"\x00\xff", t),
+                record(b"abc", t + Duration::from_secs(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn not_a_capture() {
+        // Some other gzip file
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gz.write_all(b"hello").unwrap();
+        let file = gz.finish().unwrap();
+        let error = Reader::new(file.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), InvalidData);
+
+        // Not gzip at all
+        let error = Reader::new(crate::test_data::ENUMERATION_SEQUENCE).unwrap_err();
+        assert_eq!(error.kind(), InvalidData);
+        let error = Reader::new(&b"\x7e\x07"[..]).unwrap_err();
+        assert_eq!(error.kind(), InvalidData);
+    }
+
+    #[test]
+    fn merge() {
+        let t = UNIX_EPOCH + Duration::from_secs(1723500000);
+        let a = capture(&[(b"a1", t), (b"a2", t + Duration::from_secs(2))]);
+        let b = capture(&[
+            (b"b1", t),
+            (b"b2", t + Duration::from_secs(1)),
+            (b"b3", t + Duration::from_secs(3)),
+        ]);
+
+        let merged = Merge::new([
+            Reader::new(a.as_slice()).unwrap(),
+            Reader::new(b.as_slice()).unwrap(),
+        ])
+        .map(|record| record.unwrap().data)
+        .collect::<Vec<_>>();
+        assert_eq!(merged, vec![b"a1", b"b1", b"b2", b"a2", b"b3"]);
+    }
+
+    #[test]
+    fn summary() {
+        let t = UNIX_EPOCH + Duration::from_secs(1723500000);
+        let (first, second) = crate::test_data::ENUMERATION_SEQUENCE.split_at(100);
+        let file = capture(&[(first, t), (second, t + Duration::from_secs(5))]);
+
+        let summary = Summary::of(Reader::new(file.as_slice()).unwrap()).unwrap();
+        assert_eq!(summary.records, 2);
+        assert_eq!(
+            summary.bytes,
+            crate::test_data::ENUMERATION_SEQUENCE.len() as u64
+        );
+        assert_eq!(summary.first, Some(t));
+        assert_eq!(summary.duration(), Duration::from_secs(5));
+        assert!(summary.counters.frames > 0);
+        assert_eq!(summary.counters.checksums, 0);
+    }
+
+    #[test]
+    fn replay_pacing() {
+        let t = UNIX_EPOCH + Duration::from_secs(1723500000);
+        let file = capture(&[(b"a", t), (b"b", t + Duration::from_secs(10))]);
+
+        // As fast as possible
+        let start = Instant::now();
+        let replay = Replay::new(Reader::new(file.as_slice()).unwrap(), 0.0);
+        assert_eq!(replay.count(), 2);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // 10 seconds at 100x takes 100 ms
+        let start = Instant::now();
+        let replay = Replay::new(Reader::new(file.as_slice()).unwrap(), 100.0);
+        let records = replay.collect::<std::io::Result<Vec<_>>>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(start.elapsed() >= Duration::from_millis(100));
     }
 }
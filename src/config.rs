@@ -1,13 +1,29 @@
 use crate::gateway;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+mod reader;
+#[cfg(feature = "async")]
+pub use reader::AsyncSourceReader;
+pub use reader::{ConnectionEvent, DisconnectReason, ReconnectConfig, SourceError, SourceReader};
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
-#[serde(rename = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum SourceConfig {
     #[cfg(feature = "serialport")]
     Serial(SerialSourceConfig),
+    #[cfg(unix)]
+    Termios(TermiosSourceConfig),
     Tcp(TcpConnectionConfig),
+    TcpListen(TcpListenConfig),
+    Stream(StreamSourceConfig),
+    /// Trace the serial port of a local process, normally `meshdcd` on a Tigo controller.
+    #[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+    TraceMeshdcd {
+        #[serde(default = "default_process_name")]
+        process_name: String,
+    },
 }
 
 impl SourceConfig {
@@ -15,40 +31,257 @@ impl SourceConfig {
         match self {
             #[cfg(feature = "serialport")]
             SourceConfig::Serial(config) => {
-                let conn = gateway::physical::serialport::Port::open(&config.name)?;
+                let conn = gateway::physical::serialport::Port::open(&config.name, &config.line)?;
+                Ok(Box::new(conn))
+            }
+            #[cfg(unix)]
+            SourceConfig::Termios(config) => {
+                let conn = gateway::physical::termios::Port::open(&config.path, &config.line)?;
                 Ok(Box::new(conn))
             }
             SourceConfig::Tcp(config) => {
                 let addr = (config.hostname.as_str(), config.port);
-                let readonly = match config.mode {
-                    ConnectionMode::ReadWrite => false,
-                    ConnectionMode::ReadOnly => true,
+                let readonly = config.mode == ConnectionMode::ReadOnly;
+                let conn = gateway::physical::tcp::Connection::connect(
+                    addr,
+                    readonly,
+                    config.keepalive(),
+                )?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::TcpListen(config) => {
+                let conn = config.bind()?.accept()?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::Stream(config) => {
+                let conn = match &config.input {
+                    StreamInput::Stdin => gateway::physical::stream::Stream::stdin(config.follow),
+                    StreamInput::File(path) => {
+                        gateway::physical::stream::Stream::open(path, config.follow)?
+                    }
                 };
+                Ok(Box::new(conn))
+            }
+            #[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+            SourceConfig::TraceMeshdcd { process_name } => {
+                let conn = gateway::physical::trace_meshdcd::open(process_name)
+                    .map_err(std::io::Error::other)?;
+                Ok(Box::new(conn))
+            }
+        }
+    }
 
-                let keepalive = TcpKeepaliveConfig {
-                    idle: std::time::Duration::from_secs(config.keepalive_idle),
-                    interval: std::time::Duration::from_secs(config.keepalive_interval),
-                    count: config.keepalive_count,
-                };
+    /// Open the source for transmitting as well as receiving.
+    ///
+    /// TCP sources must be configured with `mode: readwrite`. Raw byte streams and traced processes
+    /// can't transmit.
+    #[cfg(feature = "transmit")]
+    pub fn open_read_write(
+        &self,
+    ) -> Result<Box<dyn gateway::physical::ReadWriteConnection>, std::io::Error> {
+        use std::io::{Error, ErrorKind};
+
+        match self {
+            #[cfg(feature = "serialport")]
+            SourceConfig::Serial(config) => {
+                let conn = gateway::physical::serialport::Port::open(&config.name, &config.line)?;
+                Ok(Box::new(conn))
+            }
+            #[cfg(unix)]
+            SourceConfig::Termios(config) => {
+                let conn = gateway::physical::termios::Port::open(&config.path, &config.line)?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::Tcp(config) if config.mode == ConnectionMode::ReadWrite => {
+                let addr = (config.hostname.as_str(), config.port);
+                let conn =
+                    gateway::physical::tcp::Connection::connect(addr, false, config.keepalive())?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::TcpListen(config) if config.mode == ConnectionMode::ReadWrite => {
+                let conn = config.bind()?.accept()?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::Tcp(_) | SourceConfig::TcpListen(_) => Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is configured read-only", self),
+            )),
+            _ => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{} can't transmit", self),
+            )),
+        }
+    }
+
+    /// Open the source for use with tokio.
+    ///
+    /// Serial ports and TCP connections are read asynchronously. Sources which can only be read by
+    /// blocking are read on a thread of their own, see `gateway::physical::threaded`.
+    #[cfg(feature = "async")]
+    pub async fn open_async(
+        &self,
+    ) -> Result<Box<dyn gateway::physical::AsyncConnection>, std::io::Error> {
+        use gateway::physical::threaded::Threaded;
 
-                let conn = gateway::physical::tcp::Connection::connect(addr, readonly, keepalive)?;
+        match self {
+            #[cfg(all(feature = "serialport", unix))]
+            SourceConfig::Serial(config) => {
+                let conn = gateway::physical::termios::AsyncPort::open(&config.name, &config.line)?;
+                Ok(Box::new(conn))
+            }
+            #[cfg(all(feature = "serialport", not(unix)))]
+            SourceConfig::Serial(config) => {
+                let config = config.clone();
+                let conn = Threaded::open("serial", move || {
+                    gateway::physical::serialport::Port::open(&config.name, &config.line)
+                })
+                .await?;
+                Ok(Box::new(conn))
+            }
+            #[cfg(unix)]
+            SourceConfig::Termios(config) => {
+                let conn = gateway::physical::termios::AsyncPort::open(&config.path, &config.line)?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::Tcp(config) => {
+                let addr = (config.hostname.as_str(), config.port);
+                let readonly = config.mode == ConnectionMode::ReadOnly;
+                let conn = gateway::physical::tcp::AsyncConnection::connect(
+                    addr,
+                    readonly,
+                    config.keepalive(),
+                )
+                .await?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::TcpListen(config) => {
+                let conn = config.bind_async().await?.accept().await?;
+                Ok(Box::new(conn))
+            }
+            SourceConfig::Stream(config) => {
+                let config = config.clone();
+                let conn = Threaded::open("stream", move || match &config.input {
+                    StreamInput::Stdin => {
+                        Ok(gateway::physical::stream::Stream::stdin(config.follow))
+                    }
+                    StreamInput::File(path) => {
+                        gateway::physical::stream::Stream::open(path, config.follow)
+                    }
+                })
+                .await?;
                 Ok(Box::new(conn))
             }
+            #[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+            SourceConfig::TraceMeshdcd { process_name } => {
+                let process_name = process_name.clone();
+                let conn = Threaded::open("trace_meshdcd", move || {
+                    gateway::physical::trace_meshdcd::open(&process_name)
+                        .map_err(std::io::Error::other)
+                })
+                .await?;
+                Ok(Box::new(conn))
+            }
+        }
+    }
+
+    /// Whether the end of this source's data is final, rather than a reason to reconnect.
+    pub fn ends_at_eof(&self) -> bool {
+        matches!(self, SourceConfig::Stream(config) if !config.follow)
+    }
+}
+
+impl std::fmt::Display for SourceConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "serialport")]
+            SourceConfig::Serial(config) => write!(f, "serial {}", config.name),
+            #[cfg(unix)]
+            SourceConfig::Termios(config) => write!(f, "tty {}", config.path.display()),
+            SourceConfig::Tcp(config) => write!(f, "tcp {}:{}", config.hostname, config.port),
+            SourceConfig::TcpListen(config) => write!(f, "listen {}", config.address),
+            SourceConfig::Stream(StreamSourceConfig {
+                input: StreamInput::Stdin,
+                ..
+            }) => write!(f, "stdin"),
+            SourceConfig::Stream(StreamSourceConfig {
+                input: StreamInput::File(path),
+                ..
+            }) => write!(f, "file {}", path.display()),
+            #[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+            SourceConfig::TraceMeshdcd { process_name } => {
+                write!(f, "trace_meshdcd {}", process_name)
+            }
+        }
+    }
+}
+
+/// Several sources observed at once, e.g. separate installations, each with its own bus.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ObserveConfig {
+    pub sources: Vec<ObservedSourceConfig>,
+}
+
+impl ObserveConfig {
+    /// Check that every source has a distinct, non-empty name, so that events can be told apart.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sources.is_empty() {
+            return Err("no sources".into());
+        }
+
+        let mut names = std::collections::BTreeSet::new();
+        for observed in &self.sources {
+            if observed.name.is_empty() {
+                return Err("source with an empty name".into());
+            }
+            if !names.insert(observed.name.as_str()) {
+                return Err(format!("duplicate source name {:?}", observed.name));
+            }
         }
+
+        Ok(())
     }
 }
 
+/// One of several sources observed at once.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ObservedSourceConfig {
+    /// The label added to every event observed on this source.
+    pub name: String,
+    pub source: SourceConfig,
+    /// Path of the JSON file storing this source's infrastructure data, if any.
+    #[serde(default)]
+    pub persistent_file: String,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[cfg(feature = "serialport")]
 pub struct SerialSourceConfig {
     pub name: String,
+    #[serde(flatten)]
+    pub line: SerialLineConfig,
 }
+#[cfg(feature = "serialport")]
 impl From<SerialSourceConfig> for SourceConfig {
     fn from(value: SerialSourceConfig) -> Self {
         SourceConfig::Serial(value)
     }
 }
 
+/// A serial port opened using `termios` directly, which works without the `serialport` feature.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[cfg(unix)]
+pub struct TermiosSourceConfig {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub line: SerialLineConfig,
+}
+#[cfg(unix)]
+impl From<TermiosSourceConfig> for SourceConfig {
+    fn from(value: TermiosSourceConfig) -> Self {
+        SourceConfig::Termios(value)
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct TcpConnectionConfig {
     pub hostname: String,
@@ -68,6 +301,164 @@ impl From<TcpConnectionConfig> for SourceConfig {
     }
 }
 
+impl TcpConnectionConfig {
+    fn keepalive(&self) -> TcpKeepaliveConfig {
+        TcpKeepaliveConfig::from_secs(
+            self.keepalive_idle,
+            self.keepalive_interval,
+            self.keepalive_count,
+        )
+    }
+}
+
+/// Serial line settings, shared by the serial port sources.
+///
+/// The defaults match Tigo gateways: 38400 baud, 8 data bits, no parity and one stop bit.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SerialLineConfig {
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    #[serde(default)]
+    pub parity: Parity,
+    #[serde(default)]
+    pub stop_bits: StopBits,
+    /// How long to wait for further bytes before returning those already received, in
+    /// milliseconds.
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: u64,
+    /// Ask the driver to pass received bytes on immediately, rather than buffering them (Linux only).
+    #[serde(default)]
+    pub low_latency: bool,
+    /// Put the UART into RS-485 mode using `TIOCSRS485` (Linux only).
+    #[serde(default)]
+    pub rs485: Option<Rs485Config>,
+}
+
+impl Default for SerialLineConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: default_baud_rate(),
+            parity: Parity::default(),
+            stop_bits: StopBits::default(),
+            read_timeout: default_read_timeout(),
+            low_latency: false,
+            rs485: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum StopBits {
+    #[default]
+    #[cfg_attr(feature = "clap", value(name = "1"))]
+    One,
+    #[cfg_attr(feature = "clap", value(name = "2"))]
+    Two,
+}
+
+/// RS-485 settings for UARTs whose driver controls the transceiver's driver-enable line.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Rs485Config {
+    /// Drive RTS low, rather than high, while sending.
+    #[serde(default)]
+    pub rts_active_low: bool,
+    /// Keep receiving while sending, for transceivers which need it.
+    #[serde(default)]
+    pub rx_during_tx: bool,
+    /// Delay between enabling the driver and sending, in milliseconds.
+    #[serde(default)]
+    pub delay_rts_before_send: u32,
+    /// Delay between sending and disabling the driver, in milliseconds.
+    #[serde(default)]
+    pub delay_rts_after_send: u32,
+}
+
+/// A TCP source which waits for the device to connect to us.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TcpListenConfig {
+    /// The local address and port on which to listen, e.g. `0.0.0.0:7160`.
+    pub address: String,
+    pub mode: ConnectionMode,
+    #[serde(default = "default_keepalive_idle")]
+    pub keepalive_idle: u64,
+    #[serde(default = "default_keepalive_interval")]
+    pub keepalive_interval: u64,
+    #[serde(default = "default_keepalive_count")]
+    pub keepalive_count: u32,
+}
+impl From<TcpListenConfig> for SourceConfig {
+    fn from(value: TcpListenConfig) -> Self {
+        Self::TcpListen(value)
+    }
+}
+
+impl TcpListenConfig {
+    /// Start listening.
+    ///
+    /// `SourceConfig::open()` listens for a single connection. Callers which accept connections one
+    /// after another should keep the `Listener` instead, so the port stays open in between.
+    pub fn bind(&self) -> Result<gateway::physical::tcp::Listener, std::io::Error> {
+        let readonly = self.mode == ConnectionMode::ReadOnly;
+        gateway::physical::tcp::Listener::bind(self.address.as_str(), readonly, self.keepalive())
+    }
+
+    /// Start listening, for use with tokio.
+    #[cfg(feature = "async")]
+    pub async fn bind_async(
+        &self,
+    ) -> Result<gateway::physical::tcp::AsyncListener, std::io::Error> {
+        let readonly = self.mode == ConnectionMode::ReadOnly;
+        gateway::physical::tcp::AsyncListener::bind(
+            self.address.as_str(),
+            readonly,
+            self.keepalive(),
+        )
+        .await
+    }
+
+    fn keepalive(&self) -> TcpKeepaliveConfig {
+        TcpKeepaliveConfig::from_secs(
+            self.keepalive_idle,
+            self.keepalive_interval,
+            self.keepalive_count,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StreamSourceConfig {
+    pub input: StreamInput,
+    /// Keep waiting for more data at the end of the input, like `tail -f`.
+    #[serde(default)]
+    pub follow: bool,
+}
+impl From<StreamSourceConfig> for SourceConfig {
+    fn from(value: StreamSourceConfig) -> Self {
+        Self::Stream(value)
+    }
+}
+
+/// The input of a raw byte stream source.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamInput {
+    Stdin,
+    /// A regular file or a named pipe.
+    File(PathBuf),
+}
+
 /// Configuration options for TCP keepalive.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct TcpKeepaliveConfig {
@@ -79,10 +470,33 @@ pub struct TcpKeepaliveConfig {
     pub count: u32,
 }
 
+impl TcpKeepaliveConfig {
+    fn from_secs(idle: u64, interval: u64, count: u32) -> Self {
+        Self {
+            idle: std::time::Duration::from_secs(idle),
+            interval: std::time::Duration::from_secs(interval),
+            count,
+        }
+    }
+}
+
+fn default_baud_rate() -> u32 {
+    38400
+}
+
+fn default_read_timeout() -> u64 {
+    5
+}
+
 fn default_port() -> u16 {
     502
 }
 
+#[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+fn default_process_name() -> String {
+    gateway::physical::trace_meshdcd::DEFAULT_PROCESS_NAME.into()
+}
+
 fn default_keepalive_idle() -> u64 {
     30
 }
@@ -103,3 +517,69 @@ pub enum ConnectionMode {
     #[serde(rename = "readwrite", alias = "rw")]
     ReadWrite,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "transmit")]
+    fn open_read_write() {
+        let tcp = |mode| {
+            SourceConfig::Tcp(TcpConnectionConfig {
+                hostname: "127.0.0.1".into(),
+                port: 1,
+                mode,
+                keepalive_idle: default_keepalive_idle(),
+                keepalive_interval: default_keepalive_interval(),
+                keepalive_count: default_keepalive_count(),
+            })
+        };
+        let error = tcp(ConnectionMode::ReadOnly).open_read_write().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+
+        // Attempted, but nothing is listening
+        let error = tcp(ConnectionMode::ReadWrite)
+            .open_read_write()
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused);
+
+        let stdin = SourceConfig::Stream(StreamSourceConfig {
+            input: StreamInput::Stdin,
+            follow: false,
+        });
+        let error = stdin.open_read_write().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn observe_config() {
+        let config: ObserveConfig = serde_json::from_str(
+            r#"{
+                "sources": [
+                    {
+                        "name": "cca",
+                        "source": {"tcp": {"hostname": "192.0.2.1", "mode": "readonly"}},
+                        "persistent_file": "cca.json"
+                    },
+                    {
+                        "name": "goodwe",
+                        "source": {"tcp_listen": {"address": "0.0.0.0:7160", "mode": "ro"}}
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.sources[0].persistent_file, "cca.json");
+        assert!(matches!(
+            &config.sources[0].source,
+            SourceConfig::Tcp(TcpConnectionConfig { hostname, port: 502, .. }) if hostname == "192.0.2.1"
+        ));
+        assert_eq!(config.sources[1].persistent_file, "");
+
+        let mut duplicate = config.clone();
+        duplicate.sources[1].name = "cca".into();
+        assert!(duplicate.validate().is_err());
+    }
+}
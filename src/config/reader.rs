@@ -0,0 +1,873 @@
+//! Reading sources continuously, reopening them when they fail.
+//!
+//! A [`SourceReader`] keeps reading a source for as long as it provides data, reopening it when
+//! the connection is closed, fails, or goes quiet. Reopening is retried with exponential backoff,
+//! and given up once too many attempts in a row have failed. What happens to the connection is
+//! logged, and can also be followed with a callback receiving [`ConnectionEvent`]s.
+
+use super::SourceConfig;
+use crate::gateway::link;
+use crate::gateway::physical::{tcp::Listener, Connection};
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "async")]
+use {
+    crate::gateway::physical::{tcp::AsyncListener, AsyncConnection},
+    tokio::io::AsyncReadExt,
+};
+
+/// When and how often to reopen a source.
+///
+/// The defaults match those of the `taptap` command line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReconnectConfig {
+    /// Reopen the source when no data has arrived for this long.
+    pub idle_timeout: Option<Duration>,
+    /// Reopen the source when bytes keep arriving, but no valid frame has been decoded from them
+    /// for this long while line noise or checksum errors were counted.
+    ///
+    /// This catches connections which are alive but useless, like a glitching converter sending
+    /// garbage, or an RS-485 pair with A and B swapped.
+    pub frame_timeout: Option<Duration>,
+    /// How long to wait before the first attempt to reopen the source.
+    pub delay: Duration,
+    /// The longest wait between attempts. The delay doubles after each failed attempt up to this.
+    pub max_delay: Duration,
+    /// Vary each delay randomly by up to this fraction of it, so that many readers restarted at
+    /// once don't all reconnect at the same moment.
+    pub jitter: f64,
+    /// How many times in a row to reopen the source without receiving any data before giving up.
+    pub max_retries: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Some(Duration::from_secs(60)),
+            frame_timeout: None,
+            delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.1,
+            max_retries: None,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// The delay before the `retry`th attempt in a row, without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        use std::hash::{BuildHasher, Hasher};
+
+        // Every `RandomState` is keyed differently, which is all the randomness this needs
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish() as f64
+            / u64::MAX as f64;
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        delay.mul_f64(1.0 + jitter * (2.0 * random - 1.0))
+    }
+}
+
+/// Something which happened to the connection of a `SourceReader`.
+#[derive(Debug)]
+pub enum ConnectionEvent<'a> {
+    /// The source is being opened. `attempt` counts from 1 since data was last received.
+    Connecting { attempt: u32 },
+    /// The source has been opened.
+    Connected,
+    /// The source couldn't be opened.
+    ConnectFailed(&'a Error),
+    /// No data has arrived for this long, so the connection is being reopened.
+    IdleTimeout(Duration),
+    /// Bytes arrived, but no valid frames for this long, so the connection is being reopened.
+    FrameTimeout(Duration, &'a link::Counters),
+    /// The connection was lost.
+    Disconnected(&'a DisconnectReason),
+    /// The source will be reopened after this delay.
+    Reconnecting { delay: Duration },
+    /// Too many attempts in a row have failed, and the reader gives up.
+    GaveUp { retries: u32 },
+    /// The source ended, and won't be reopened.
+    EndOfInput,
+}
+
+/// Why a connection was lost.
+#[derive(thiserror::Error, Debug)]
+pub enum DisconnectReason {
+    #[error("connection closed by peer")]
+    ClosedByPeer,
+    #[error("error reading: {0}")]
+    Error(#[source] Error),
+}
+
+/// The error returned once a `SourceReader` gives up.
+#[derive(thiserror::Error, Debug)]
+pub enum SourceError {
+    #[error("maximum reconnect retries ({retries}) exceeded, last error opening source: {error}")]
+    Open {
+        retries: u32,
+        #[source]
+        error: Error,
+    },
+    #[error(
+        "maximum reconnect retries ({retries}) exceeded, last connection idle for {timeout:?}"
+    )]
+    Idle { retries: u32, timeout: Duration },
+    #[error(
+        "maximum reconnect retries ({retries}) exceeded, last connection had no valid frames for {timeout:?}"
+    )]
+    NoFrames { retries: u32, timeout: Duration },
+    #[error("maximum reconnect retries ({retries}) exceeded, last connection lost: {reason}")]
+    Disconnected {
+        retries: u32,
+        #[source]
+        reason: DisconnectReason,
+    },
+}
+
+/// The failure count shared by the blocking and async readers.
+#[derive(Debug)]
+struct Retries {
+    config: ReconnectConfig,
+    count: u32,
+}
+
+impl Retries {
+    fn new(config: ReconnectConfig) -> Self {
+        Self { config, count: 0 }
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    /// Count a failure, returning how long to wait before the next attempt, or `None` to give up.
+    fn fail(&mut self) -> Option<Duration> {
+        self.count += 1;
+        match self.config.max_retries {
+            Some(max_retries) if self.count > max_retries => {
+                log::warn!("maximum reconnect retries ({}) exceeded", max_retries);
+                None
+            }
+            max_retries => {
+                log::info!(
+                    "reconnect retry {}/{}",
+                    self.count,
+                    max_retries.map_or("∞".to_string(), |max| max.to_string())
+                );
+                let delay = self.config.jittered(self.config.backoff(self.count));
+                log::info!("reconnecting in {:?}...", delay);
+                Some(delay)
+            }
+        }
+    }
+}
+
+/// Decodes the bytes read at the link layer, to notice connections which carry only garbage.
+#[derive(Debug)]
+struct FrameWatch {
+    timeout: Option<Duration>,
+    receiver: link::Receiver<()>,
+    last_frame: Instant,
+}
+
+impl FrameWatch {
+    fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            receiver: link::Receiver::new(()),
+            last_frame: Instant::now(),
+        }
+    }
+
+    /// Start watching a new connection.
+    fn reset(&mut self) {
+        self.receiver = link::Receiver::new(());
+        self.last_frame = Instant::now();
+    }
+
+    /// Decode `bytes`, and tell whether the connection is carrying valid frames.
+    fn check(&mut self, bytes: &[u8]) -> Watch {
+        let Some(timeout) = self.timeout else {
+            return Watch::Frames;
+        };
+
+        let frames = self.receiver.counters().frames;
+        self.receiver.extend_from_slice(bytes);
+        let counters = *self.receiver.counters();
+        if counters.frames != frames {
+            // Only count errors since the last valid frame
+            self.receiver.reset_counters();
+            self.last_frame = Instant::now();
+            return Watch::Frames;
+        }
+
+        let errors = counters.noise + counters.checksums;
+        if errors > 0 && self.last_frame.elapsed() >= timeout {
+            Watch::TimedOut(timeout)
+        } else {
+            Watch::Waiting
+        }
+    }
+
+    fn counters(&self) -> &link::Counters {
+        self.receiver.counters()
+    }
+}
+
+/// What a `FrameWatch` made of the bytes read.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Watch {
+    /// A valid frame decoded, or frames aren't being watched: the connection works.
+    Frames,
+    /// No valid frame decoded yet.
+    Waiting,
+    /// The frame timeout has passed without a valid frame while errors were counted.
+    TimedOut(Duration),
+}
+
+/// What a read from a connection amounted to.
+enum Outcome {
+    Data(usize),
+    EndOfInput,
+    Disconnected(DisconnectReason),
+    Retry,
+}
+
+impl Outcome {
+    fn of(result: Result<usize, Error>, source: &SourceConfig) -> Self {
+        match result {
+            Ok(0) if source.ends_at_eof() => Outcome::EndOfInput,
+            Ok(0) => Outcome::Disconnected(DisconnectReason::ClosedByPeer),
+            Ok(n) => Outcome::Data(n),
+            Err(e) if e.kind() == ErrorKind::Interrupted => Outcome::Retry,
+            Err(e) => Outcome::Disconnected(DisconnectReason::Error(e)),
+        }
+    }
+}
+
+/// Reads a source, reopening it whenever it closes, fails or goes quiet.
+pub struct SourceReader<F = fn(&ConnectionEvent)> {
+    source: SourceConfig,
+    conn: Option<Box<dyn Connection>>,
+    listener: Option<Listener>,
+    last_received: Instant,
+    retries: Retries,
+    frames: FrameWatch,
+    buffer: Vec<u8>,
+    events: F,
+}
+
+impl SourceReader {
+    pub fn new(source: SourceConfig, reconnect: ReconnectConfig) -> Self {
+        Self::with_events(source, reconnect, |_| {})
+    }
+}
+
+impl<F: FnMut(&ConnectionEvent)> SourceReader<F> {
+    /// Instantiate a reader which also reports what happens to its connection to `events`.
+    pub fn with_events(source: SourceConfig, reconnect: ReconnectConfig, events: F) -> Self {
+        Self {
+            source,
+            conn: None,
+            listener: None,
+            last_received: Instant::now(),
+            retries: Retries::new(reconnect),
+            frames: FrameWatch::new(reconnect.frame_timeout),
+            buffer: vec![0; 1024],
+            events,
+        }
+    }
+
+    pub fn source(&self) -> &SourceConfig {
+        &self.source
+    }
+
+    /// Read the next slice of bytes and the time at which it was received, opening the source
+    /// as needed.
+    ///
+    /// Returns `Ok(None)` at the end of a source which `ends_at_eof()`, and an error once
+    /// `max_retries` has been exceeded.
+    ///
+    /// The idle timeout is checked whenever a read times out, so it only applies to connections
+    /// with a read timeout, like TCP connections.
+    pub fn read(&mut self) -> Result<Option<(&[u8], SystemTime)>, SourceError> {
+        loop {
+            let conn = match &mut self.conn {
+                Some(conn) => conn,
+                None => {
+                    (self.events)(&ConnectionEvent::Connecting {
+                        attempt: self.retries.count + 1,
+                    });
+                    log::info!("opening source connection...");
+                    match self.open() {
+                        Ok(conn) => {
+                            log::info!("source opened, entering read loop");
+                            (self.events)(&ConnectionEvent::Connected);
+                            self.last_received = Instant::now();
+                            self.frames.reset();
+                            self.conn.insert(conn)
+                        }
+                        Err(error) => {
+                            log::error!("error opening source: {}", error);
+                            (self.events)(&ConnectionEvent::ConnectFailed(&error));
+                            self.retry(|retries| SourceError::Open { retries, error })?;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let result = match conn.read(&mut self.buffer) {
+                Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                    match self.retries.config.idle_timeout {
+                        Some(timeout) if self.last_received.elapsed() >= timeout => {
+                            self.idle(timeout)?;
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                result => result,
+            };
+
+            match Outcome::of(result, &self.source) {
+                Outcome::Data(n) => {
+                    self.last_received = Instant::now();
+                    // Only a connection which carries frames makes up for earlier failures
+                    match self.frames.check(&self.buffer[..n]) {
+                        Watch::Frames => self.retries.reset(),
+                        Watch::Waiting => {}
+                        Watch::TimedOut(timeout) => {
+                            self.no_frames(timeout)?;
+                            continue;
+                        }
+                    }
+                    return Ok(Some((&self.buffer[..n], SystemTime::now())));
+                }
+                Outcome::EndOfInput => {
+                    self.end_of_input();
+                    return Ok(None);
+                }
+                Outcome::Disconnected(reason) => self.disconnected(reason)?,
+                Outcome::Retry => {}
+            }
+        }
+    }
+
+    /// Pass every slice of bytes read to `callback`, until the end of a source which
+    /// `ends_at_eof()`, or until giving up.
+    pub fn run<C: FnMut(&[u8], SystemTime)>(&mut self, mut callback: C) -> Result<(), SourceError> {
+        while let Some((slice, timestamp)) = self.read()? {
+            callback(slice, timestamp);
+        }
+        Ok(())
+    }
+
+    /// Open the source, keeping a `TcpListen` socket open between connections.
+    fn open(&mut self) -> Result<Box<dyn Connection>, Error> {
+        let SourceConfig::TcpListen(config) = &self.source else {
+            return self.source.open();
+        };
+
+        let listener = match &mut self.listener {
+            Some(listener) => listener,
+            None => {
+                let bound = config.bind()?;
+                log::info!("listening on {}", bound.local_addr()?);
+                self.listener.insert(bound)
+            }
+        };
+
+        let conn = listener.accept()?;
+        log::info!("accepted connection from {}", conn.peer_addr()?);
+        Ok(Box::new(conn))
+    }
+
+    fn idle(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        log::warn!("no data for {:?}, reconnecting (idle timeout)", timeout);
+        (self.events)(&ConnectionEvent::IdleTimeout(timeout));
+        self.conn = None;
+        self.retry(|retries| SourceError::Idle { retries, timeout })
+    }
+
+    fn no_frames(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        let counters = *self.frames.counters();
+        log::warn!(
+            "no valid frames for {:?} ({} noise, {} checksum errors), reconnecting (frame timeout)",
+            timeout,
+            counters.noise,
+            counters.checksums
+        );
+        (self.events)(&ConnectionEvent::FrameTimeout(timeout, &counters));
+        self.conn = None;
+        self.retry(|retries| SourceError::NoFrames { retries, timeout })
+    }
+
+    fn disconnected(&mut self, reason: DisconnectReason) -> Result<(), SourceError> {
+        log::warn!("{}, will reconnect", reason);
+        (self.events)(&ConnectionEvent::Disconnected(&reason));
+        self.conn = None;
+        self.retry(|retries| SourceError::Disconnected { retries, reason })
+    }
+
+    fn end_of_input(&mut self) {
+        log::info!("end of input");
+        (self.events)(&ConnectionEvent::EndOfInput);
+        self.conn = None;
+    }
+
+    /// Count a failed attempt, and wait before the next one if any remain.
+    fn retry(&mut self, error: impl FnOnce(u32) -> SourceError) -> Result<(), SourceError> {
+        match self.retries.fail() {
+            Some(delay) => {
+                (self.events)(&ConnectionEvent::Reconnecting { delay });
+                std::thread::sleep(delay);
+                Ok(())
+            }
+            None => {
+                let retries = self.retries.count - 1;
+                (self.events)(&ConnectionEvent::GaveUp { retries });
+                Err(error(retries))
+            }
+        }
+    }
+}
+
+impl<F> std::fmt::Debug for SourceReader<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SourceReader")
+            .field("source", &self.source)
+            .field("conn", &self.conn)
+            .field("retries", &self.retries)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads a source with tokio, reopening it whenever it closes, fails or goes quiet.
+///
+/// This behaves like a `SourceReader`, except that the idle timeout applies to every connection.
+#[cfg(feature = "async")]
+pub struct AsyncSourceReader<F = fn(&ConnectionEvent)> {
+    source: SourceConfig,
+    conn: Option<Box<dyn AsyncConnection>>,
+    listener: Option<AsyncListener>,
+    retries: Retries,
+    frames: FrameWatch,
+    buffer: Vec<u8>,
+    events: F,
+}
+
+#[cfg(feature = "async")]
+impl AsyncSourceReader {
+    pub fn new(source: SourceConfig, reconnect: ReconnectConfig) -> Self {
+        Self::with_events(source, reconnect, |_| {})
+    }
+}
+
+#[cfg(feature = "async")]
+impl<F: FnMut(&ConnectionEvent)> AsyncSourceReader<F> {
+    /// Instantiate a reader which also reports what happens to its connection to `events`.
+    pub fn with_events(source: SourceConfig, reconnect: ReconnectConfig, events: F) -> Self {
+        Self {
+            source,
+            conn: None,
+            listener: None,
+            retries: Retries::new(reconnect),
+            frames: FrameWatch::new(reconnect.frame_timeout),
+            buffer: vec![0; 1024],
+            events,
+        }
+    }
+
+    pub fn source(&self) -> &SourceConfig {
+        &self.source
+    }
+
+    /// Read the next slice of bytes and the time at which it was received, opening the source
+    /// as needed.
+    ///
+    /// Returns `Ok(None)` at the end of a source which `ends_at_eof()`, and an error once
+    /// `max_retries` has been exceeded.
+    pub async fn read(&mut self) -> Result<Option<(&[u8], SystemTime)>, SourceError> {
+        loop {
+            let conn = match &mut self.conn {
+                Some(conn) => conn,
+                None => {
+                    (self.events)(&ConnectionEvent::Connecting {
+                        attempt: self.retries.count + 1,
+                    });
+                    log::info!("opening source connection...");
+                    match self.open().await {
+                        Ok(conn) => {
+                            log::info!("source opened, entering read loop");
+                            (self.events)(&ConnectionEvent::Connected);
+                            self.frames.reset();
+                            self.conn.insert(conn)
+                        }
+                        Err(error) => {
+                            log::error!("error opening source: {}", error);
+                            (self.events)(&ConnectionEvent::ConnectFailed(&error));
+                            self.retry(|retries| SourceError::Open { retries, error })
+                                .await?;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let result = match self.retries.config.idle_timeout {
+                Some(timeout) => {
+                    match tokio::time::timeout(timeout, conn.read(&mut self.buffer)).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            self.idle(timeout).await?;
+                            continue;
+                        }
+                    }
+                }
+                None => conn.read(&mut self.buffer).await,
+            };
+
+            match Outcome::of(result, &self.source) {
+                Outcome::Data(n) => {
+                    // Only a connection which carries frames makes up for earlier failures
+                    match self.frames.check(&self.buffer[..n]) {
+                        Watch::Frames => self.retries.reset(),
+                        Watch::Waiting => {}
+                        Watch::TimedOut(timeout) => {
+                            self.no_frames(timeout).await?;
+                            continue;
+                        }
+                    }
+                    return Ok(Some((&self.buffer[..n], SystemTime::now())));
+                }
+                Outcome::EndOfInput => {
+                    self.end_of_input();
+                    return Ok(None);
+                }
+                Outcome::Disconnected(reason) => self.disconnected(reason).await?,
+                Outcome::Retry => {}
+            }
+        }
+    }
+
+    /// Open the source, keeping a `TcpListen` socket open between connections.
+    async fn open(&mut self) -> Result<Box<dyn AsyncConnection>, Error> {
+        let SourceConfig::TcpListen(config) = &self.source else {
+            return self.source.open_async().await;
+        };
+
+        let listener = match &mut self.listener {
+            Some(listener) => listener,
+            None => {
+                let bound = config.bind_async().await?;
+                log::info!("listening on {}", bound.local_addr()?);
+                self.listener.insert(bound)
+            }
+        };
+
+        let conn = listener.accept().await?;
+        log::info!("accepted connection from {}", conn.peer_addr()?);
+        Ok(Box::new(conn))
+    }
+
+    async fn idle(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        log::warn!("no data for {:?}, reconnecting (idle timeout)", timeout);
+        (self.events)(&ConnectionEvent::IdleTimeout(timeout));
+        self.conn = None;
+        self.retry(|retries| SourceError::Idle { retries, timeout })
+            .await
+    }
+
+    async fn no_frames(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        let counters = *self.frames.counters();
+        log::warn!(
+            "no valid frames for {:?} ({} noise, {} checksum errors), reconnecting (frame timeout)",
+            timeout,
+            counters.noise,
+            counters.checksums
+        );
+        (self.events)(&ConnectionEvent::FrameTimeout(timeout, &counters));
+        self.conn = None;
+        self.retry(|retries| SourceError::NoFrames { retries, timeout })
+            .await
+    }
+
+    async fn disconnected(&mut self, reason: DisconnectReason) -> Result<(), SourceError> {
+        log::warn!("{}, will reconnect", reason);
+        (self.events)(&ConnectionEvent::Disconnected(&reason));
+        self.conn = None;
+        self.retry(|retries| SourceError::Disconnected { retries, reason })
+            .await
+    }
+
+    fn end_of_input(&mut self) {
+        log::info!("end of input");
+        (self.events)(&ConnectionEvent::EndOfInput);
+        self.conn = None;
+    }
+
+    /// Count a failed attempt, and wait before the next one if any remain.
+    async fn retry(&mut self, error: impl FnOnce(u32) -> SourceError) -> Result<(), SourceError> {
+        match self.retries.fail() {
+            Some(delay) => {
+                (self.events)(&ConnectionEvent::Reconnecting { delay });
+                tokio::time::sleep(delay).await;
+                Ok(())
+            }
+            None => {
+                let retries = self.retries.count - 1;
+                (self.events)(&ConnectionEvent::GaveUp { retries });
+                Err(error(retries))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<F> std::fmt::Debug for AsyncSourceReader<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncSourceReader")
+            .field("source", &self.source)
+            .field("conn", &self.conn)
+            .field("retries", &self.retries)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConnectionMode, TcpConnectionConfig};
+
+    fn tcp_source(port: u16) -> SourceConfig {
+        SourceConfig::Tcp(TcpConnectionConfig {
+            hostname: "127.0.0.1".into(),
+            port,
+            mode: ConnectionMode::ReadOnly,
+            keepalive_idle: 30,
+            keepalive_interval: 10,
+            keepalive_count: 5,
+        })
+    }
+
+    fn reconnect() -> ReconnectConfig {
+        ReconnectConfig {
+            idle_timeout: Some(Duration::from_secs(5)),
+            frame_timeout: None,
+            delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(20),
+            jitter: 0.5,
+            max_retries: Some(2),
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let config = ReconnectConfig {
+            delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            ..ReconnectConfig::default()
+        };
+        let delays = (1..=6).map(|retry| config.backoff(retry).as_secs());
+        assert_eq!(delays.collect::<Vec<_>>(), vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(config.backoff(u32::MAX), Duration::from_secs(10));
+
+        for _ in 0..100 {
+            let delay = config.jittered(Duration::from_secs(10));
+            assert!(delay >= Duration::from_secs(9) && delay <= Duration::from_secs(11));
+        }
+    }
+
+    #[test]
+    fn reconnect_and_give_up() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let source = tcp_source(listener.local_addr().unwrap().port());
+        let mut events = Vec::new();
+        let mut reader = SourceReader::with_events(source, reconnect(), |event| {
+            events.push(
+                format!("{:?}", event)
+                    .split([' ', '('])
+                    .next()
+                    .unwrap()
+                    .to_owned(),
+            )
+        });
+
+        // Each connection sends a message and hangs up, after which the reader comes back
+        let server = std::thread::spawn(move || {
+            for message in [b"\x00\xff", b"\x7e\x07"] {
+                let (mut socket, _) = listener.accept().unwrap();
+                socket.write_all(message).unwrap();
+            }
+        });
+        for message in [b"\x00\xff", b"\x7e\x07"] {
+            let (slice, _) = reader.read().unwrap().unwrap();
+            assert_eq!(slice, message);
+        }
+        server.join().unwrap();
+
+        // With the listener gone, the reader gives up after the last retry
+        assert!(matches!(
+            reader.read(),
+            Err(SourceError::Open { retries: 2, .. })
+        ));
+        drop(reader);
+        assert_eq!(
+            events,
+            [
+                "Connecting",
+                "Connected",
+                "Disconnected",
+                "Reconnecting",
+                "Connecting",
+                "Connected",
+                "Disconnected",
+                "Reconnecting",
+                "Connecting",
+                "ConnectFailed",
+                "Reconnecting",
+                "Connecting",
+                "ConnectFailed",
+                "GaveUp"
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_watch() {
+        let mut watch = FrameWatch::new(Some(Duration::ZERO));
+
+        // Valid frames keep the connection alive
+        assert_eq!(
+            watch.check(crate::test_data::ENUMERATION_SEQUENCE),
+            Watch::Frames
+        );
+
+        // Bytes which never decode don't
+        assert_eq!(
+            watch.check(b"\x81\x00\x81\xf8"),
+            Watch::TimedOut(Duration::ZERO)
+        );
+
+        // Until the timeout passes, they're only waited on
+        let mut watch = FrameWatch::new(Some(Duration::from_secs(60)));
+        assert_eq!(watch.check(b"\x81\x00\x81\xf8"), Watch::Waiting);
+
+        // Without a timeout nothing is decoded
+        let mut watch = FrameWatch::new(None);
+        assert_eq!(watch.check(b"\x81\x00\x81\xf8"), Watch::Frames);
+        assert_eq!(watch.counters(), &link::Counters::default());
+    }
+
+    #[test]
+    fn no_frames() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let source = tcp_source(listener.local_addr().unwrap().port());
+        let reconnect = ReconnectConfig {
+            frame_timeout: Some(Duration::from_millis(50)),
+            max_retries: Some(0),
+            ..reconnect()
+        };
+        let mut reader = SourceReader::new(source, reconnect);
+
+        // A converter with A and B swapped sends a steady stream of inverted bytes
+        std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            while socket.write_all(b"\xff\x81\xf8\x00\x81\xf7").is_ok() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+        loop {
+            match reader.read() {
+                Ok(Some(_)) => continue,
+                Err(SourceError::NoFrames { retries: 0, .. }) => break,
+                result => panic!("unexpected {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn no_frames_across_reconnects() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let source = tcp_source(listener.local_addr().unwrap().port());
+        let reconnect = ReconnectConfig {
+            frame_timeout: Some(Duration::from_millis(50)),
+            delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            jitter: 0.0,
+            max_retries: Some(2),
+            ..reconnect()
+        };
+        let mut delays = Vec::new();
+        let mut reader = SourceReader::with_events(source, reconnect, |event| {
+            if let ConnectionEvent::Reconnecting { delay } = event {
+                delays.push(*delay);
+            }
+        });
+
+        // Every connection carries nothing but noise, which mustn't reset the retry count
+        std::thread::spawn(move || {
+            for socket in listener.incoming() {
+                let mut socket = socket.unwrap();
+                std::thread::spawn(move || {
+                    while socket.write_all(b"\xff\x81\xf8\x00\x81\xf7").is_ok() {
+                        std::thread::sleep(Duration::from_millis(5));
+                    }
+                });
+            }
+        });
+        loop {
+            match reader.read() {
+                Ok(Some(_)) => continue,
+                Err(SourceError::NoFrames { retries: 2, .. }) => break,
+                result => panic!("unexpected {:?}", result),
+            }
+        }
+        drop(reader);
+        assert_eq!(
+            delays,
+            [Duration::from_millis(10), Duration::from_millis(20)]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn reconnect_async() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let source = tcp_source(listener.local_addr().unwrap().port());
+        let mut reader = AsyncSourceReader::new(source, reconnect());
+
+        let server = tokio::spawn(async move {
+            for message in [b"\x00\xff", b"\x7e\x07"] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                socket.write_all(message).await.unwrap();
+            }
+        });
+        for message in [b"\x00\xff", b"\x7e\x07"] {
+            let (slice, _) = reader.read().await.unwrap().unwrap();
+            assert_eq!(slice, message);
+        }
+        server.await.unwrap();
+
+        assert!(matches!(
+            reader.read().await,
+            Err(SourceError::Open { retries: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,435 @@
+//! Decoding hex dumps of bus traffic into a layered breakdown.
+//!
+//! [`parse_hex()`] reads bytes written as hex, as found in bug reports and `docs/protocol.md`, and
+//! [`decode()`] runs them through the link layer `Receiver`, describing each frame field by field
+//! at the link, transport, PV network and PV application layers.
+//!
+//! Each frame is decoded on its own, so that a single frame copied from a longer exchange decodes
+//! as well as the whole exchange. The one exception is the packet number of receive responses,
+//! which may be abbreviated relative to the preceding receive request to the same gateway.
+
+use crate::gateway::link::{self, Address, ErrorKind, FrameRef, GatewayID, Type};
+use crate::gateway::transport::{
+    CommandRequest, CommandResponse, EnumerationStartRequest, IdentifyResponse, ReceiveRequest,
+    ReceiveResponse,
+};
+use crate::pv::application::{
+    NodeTableRequest, NodeTableResponse, PacketType, PowerReport, TopologyReport,
+};
+use crate::pv::network::NodeAddress;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Write};
+use std::mem::size_of;
+use zerocopy::FromBytes;
+
+/// An error parsing hex.
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq)]
+pub enum ParseHexError {
+    #[error("not hex: {0:?}")]
+    NotHex(String),
+    #[error("odd number of hex digits: {0:?}")]
+    OddLength(String),
+}
+
+/// Parse bytes written as hex.
+///
+/// Bytes may be separated by whitespace or commas, written with or without a `0x` prefix, and
+/// run together, as in `FF 7E 07`, `0xff, 0x7e, 0x07` or `ff7e07`.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, ParseHexError> {
+    let mut bytes = Vec::new();
+    for word in text.split(|c: char| c.is_whitespace() || c == ',') {
+        let digits = word
+            .strip_prefix("0x")
+            .or_else(|| word.strip_prefix("0X"))
+            .unwrap_or(word);
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseHexError::NotHex(word.into()));
+        }
+        if digits.len() % 2 != 0 {
+            return Err(ParseHexError::OddLength(word.into()));
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            // Checked above
+            let pair = std::str::from_utf8(pair).unwrap();
+            bytes.push(u8::from_str_radix(pair, 16).unwrap());
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decode `bytes` as received from the bus, returning a breakdown of each frame and line error.
+pub fn decode(bytes: &[u8]) -> String {
+    let mut rx = link::Receiver::new(Decoder::default());
+    rx.extend_from_slice(bytes);
+    // A preamble byte ends any noise at the end of the input, so that it is reported
+    rx.extend_from_slice(&[0xff]);
+
+    let counters = *rx.counters();
+    let mut decoder = rx.into_inner();
+    writeln!(
+        decoder.out,
+        "{} frames, {} runts, {} giants, {} checksum errors, {} noise",
+        counters.frames, counters.runts, counters.giants, counters.checksums, counters.noise
+    )
+    .unwrap();
+    decoder.out
+}
+
+/// A `Sink` writing out each frame as it is received.
+#[derive(Debug, Default)]
+struct Decoder {
+    out: String,
+    frames: usize,
+    /// The packet number of the latest receive request to each gateway.
+    packet_numbers: BTreeMap<GatewayID, u16>,
+}
+
+impl link::Sink for Decoder {
+    fn frame(&mut self, frame: FrameRef<'_>) {
+        self.frames += 1;
+        writeln!(self.out, "frame {}", self.frames).unwrap();
+
+        let unescaped = link::Frame::from(frame).unescaped();
+        let crc = u16::from_le_bytes([
+            unescaped[unescaped.len() - 2],
+            unescaped[unescaped.len() - 1],
+        ]);
+        self.layer("link");
+        self.field("address", frame.address);
+        self.field("type", frame.frame_type);
+        self.field("payload", Hex(frame.payload));
+        self.field("crc", format_args!("0x{:04X} (valid)", crc));
+
+        match (frame.frame_type, frame.address) {
+            (Type::RECEIVE_REQUEST, Address::To(gateway_id)) => {
+                self.receive_request(gateway_id, frame.payload)
+            }
+            (Type::RECEIVE_RESPONSE, Address::From(gateway_id)) => {
+                self.receive_response(gateway_id, frame.payload)
+            }
+            (Type::COMMAND_REQUEST, _) => self.command_request(frame.payload),
+            (Type::COMMAND_RESPONSE, _) => self.command_response(frame.payload),
+            (Type::ENUMERATION_START_REQUEST, _) => self.enumeration_start_request(frame.payload),
+            (Type::IDENTIFY_RESPONSE | Type::ENUMERATION_RESPONSE, _) => {
+                self.identify_response(frame.payload)
+            }
+            (Type::VERSION_RESPONSE, _) => {
+                self.layer("transport");
+                self.field("version", String::from_utf8_lossy(frame.payload));
+            }
+            _ => {}
+        }
+    }
+
+    fn error(&mut self, kind: ErrorKind, raw: &[u8]) {
+        writeln!(self.out, "{} error", kind).unwrap();
+        self.layer("link");
+        self.field("raw", Hex(raw));
+        if kind == ErrorKind::Checksum {
+            self.checksum_error(raw);
+        }
+    }
+}
+
+impl Decoder {
+    fn layer(&mut self, name: &str) {
+        writeln!(self.out, "  {}", name).unwrap();
+    }
+
+    fn field(&mut self, name: &str, value: impl Debug) {
+        writeln!(self.out, "    {}: {:?}", name, value).unwrap();
+    }
+
+    fn invalid(&mut self, error: impl Debug) {
+        self.field("invalid", error);
+    }
+
+    /// Break down a frame which failed its checksum, as received between its start sequence and
+    /// terminator.
+    fn checksum_error(&mut self, raw: &[u8]) {
+        let body = raw
+            .strip_prefix(&[0x7E, 0x07])
+            .and_then(|raw| raw.strip_suffix(&[0x7E, 0x08]))
+            .and_then(|escaped| link::unescape(escaped).ok());
+        let Some(body) = body.filter(|body| body.len() >= 6) else {
+            return self.invalid("not a complete frame");
+        };
+
+        let (body, crc) = body.split_at(body.len() - 2);
+        let crc = u16::from_le_bytes([crc[0], crc[1]]);
+        self.field("address", Address::from([body[0], body[1]]));
+        self.field("type", Type(u16::from_be_bytes([body[2], body[3]])));
+        self.field("payload", Hex(&body[4..]));
+        self.field(
+            "crc",
+            format_args!(
+                "0x{:04X} (invalid, computed 0x{:04X})",
+                crc,
+                link::crc(body)
+            ),
+        );
+    }
+
+    fn receive_request(&mut self, gateway_id: GatewayID, payload: &[u8]) {
+        self.layer("transport");
+        let Ok(request) = ReceiveRequest::ref_from_bytes(payload) else {
+            return self.invalid("wrong length for a receive request");
+        };
+        self.packet_numbers
+            .insert(gateway_id, request.packet_number.get());
+        self.field("packet_number", request.packet_number.get());
+        self.field("unknown_1", request.unknown_1);
+        self.field("unknown_2", request.unknown_2);
+    }
+
+    fn receive_response(&mut self, gateway_id: GatewayID, payload: &[u8]) {
+        self.layer("transport");
+        let packet_number = self.packet_numbers.get(&gateway_id).copied().unwrap_or(0);
+        let (response, packets) = match ReceiveResponse::read_from_bytes(payload, packet_number) {
+            Ok(response) => response,
+            Err(e) => return self.invalid(e),
+        };
+        self.field("rx_buffers_used", response.rx_buffers_used);
+        self.field("tx_buffers_free", response.tx_buffers_free);
+        self.field("unknown_a", response.unknown_a);
+        self.field("unknown_b", response.unknown_b);
+        self.field("packet_number", response.packet_number);
+        self.field("slot_counter", response.slot_counter);
+        self.field("packets", packets.clone().count());
+
+        for packet in packets {
+            self.layer("network");
+            let (header, data) = match packet {
+                Ok(packet) => packet,
+                Err(e) => return self.invalid(e),
+            };
+            self.field("packet_type", header.packet_type);
+            self.field("node_address", header.node_address);
+            self.field("short_address", header.short_address);
+            self.field("dsn", header.dsn);
+            self.field("data", Hex(data));
+            self.received_packet(header.packet_type, data);
+        }
+    }
+
+    fn received_packet(&mut self, packet_type: PacketType, data: &[u8]) {
+        match packet_type {
+            PacketType::POWER_REPORT => {
+                self.layer("application");
+                match PowerReport::ref_from_bytes(data) {
+                    Ok(report) => self.field("power_report", report),
+                    Err(_) => self.invalid("wrong length for a power report"),
+                }
+            }
+            PacketType::TOPOLOGY_REPORT => {
+                self.layer("application");
+                match TopologyReport::ref_from_bytes(data) {
+                    Ok(report) => self.field("topology_report", report),
+                    Err(_) => self.invalid("wrong length for a topology report"),
+                }
+            }
+            PacketType::STRING_RESPONSE => {
+                self.layer("application");
+                self.field("string_response", String::from_utf8_lossy(data));
+            }
+            _ => {}
+        }
+    }
+
+    fn command_request(&mut self, payload: &[u8]) {
+        self.layer("transport");
+        if payload.len() < size_of::<CommandRequest>() {
+            return self.invalid("too short for a command request");
+        }
+        let (header, data) = payload.split_at(size_of::<CommandRequest>());
+        let header = CommandRequest::ref_from_bytes(header).unwrap(); // infallible
+        self.field("packet_type", header.packet_type);
+        self.field("sequence_number", header.sequence_number.0);
+        self.field("unknown", header.unknown);
+        self.field("data", Hex(data));
+
+        match header.packet_type {
+            PacketType::NODE_TABLE_REQUEST => {
+                self.layer("application");
+                match NodeTableRequest::ref_from_bytes(data) {
+                    Ok(request) => self.field("start_at", request.start_at),
+                    Err(_) => self.invalid("wrong length for a node table request"),
+                }
+            }
+            PacketType::STRING_REQUEST => {
+                self.layer("application");
+                match NodeAddress::ref_from_prefix(data) {
+                    Ok((node, request)) => {
+                        self.field("node_address", node);
+                        self.field("string_request", String::from_utf8_lossy(request));
+                    }
+                    Err(_) => self.invalid("too short for a string request"),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn command_response(&mut self, payload: &[u8]) {
+        self.layer("transport");
+        if payload.len() < size_of::<CommandResponse>() {
+            return self.invalid("too short for a command response");
+        }
+        let (header, data) = payload.split_at(size_of::<CommandResponse>());
+        let header = CommandResponse::ref_from_bytes(header).unwrap(); // infallible
+        self.field("packet_type", header.packet_type);
+        self.field("sequence_number", header.command_sequence_number.0);
+        self.field("tx_buffers_free", header.tx_buffers_free);
+        self.field("unknown_1", header.unknown_1);
+        self.field("unknown_2", header.unknown_2);
+        self.field("data", Hex(data));
+
+        match header.packet_type {
+            PacketType::NODE_TABLE_RESPONSE => {
+                self.layer("application");
+                match NodeTableResponse::ref_from_bytes(data) {
+                    Ok(response) => {
+                        self.field("start_at", response.start_at);
+                        self.field("entries_count", response.entries_count.get());
+                        for entry in &response.entries {
+                            self.field("entry", entry);
+                        }
+                    }
+                    Err(_) => self.invalid("wrong length for a node table response"),
+                }
+            }
+            PacketType::STRING_RESPONSE if !data.is_empty() => {
+                self.layer("application");
+                self.field("string_response", String::from_utf8_lossy(data));
+            }
+            _ => {}
+        }
+    }
+
+    fn enumeration_start_request(&mut self, payload: &[u8]) {
+        self.layer("transport");
+        let Ok(request) = EnumerationStartRequest::ref_from_bytes(payload) else {
+            return self.invalid("wrong length for an enumeration start request");
+        };
+        self.field("enumeration_gateway_id", request.enumeration_gateway_id());
+        self.field("unknown", request.unknown);
+    }
+
+    fn identify_response(&mut self, payload: &[u8]) {
+        self.layer("transport");
+        let Ok(response) = IdentifyResponse::ref_from_bytes(payload) else {
+            return self.invalid("wrong length for an identify response");
+        };
+        self.field("pv_long_address", response.pv_long_address);
+        self.field("gateway_id", response.gateway_id());
+    }
+}
+
+/// Bytes formatted as space-separated hex, as they are written in `docs/protocol.md`.
+struct Hex<'a>(&'a [u8]);
+
+impl Debug for Hex<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(' ')?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        let expected = vec![0xFF, 0x7E, 0x07];
+        assert_eq!(parse_hex("FF 7E 07"), Ok(expected.clone()));
+        assert_eq!(parse_hex("  0xff, 0x7e,\n0x07\n"), Ok(expected.clone()));
+        assert_eq!(parse_hex("ff7e07"), Ok(expected));
+        assert_eq!(parse_hex(""), Ok(vec![]));
+        assert_eq!(
+            parse_hex("FF 7E … 08"),
+            Err(ParseHexError::NotHex("…".into()))
+        );
+        assert_eq!(
+            parse_hex("FF 7E0"),
+            Err(ParseHexError::OddLength("7E0".into()))
+        );
+    }
+
+    #[test]
+    fn receive_exchange() {
+        // From docs/protocol.md
+        let bytes = parse_hex(
+            "00 FF FF 7E 07 12 01 01 48 00 01 18 83 04 17 44 7E 08
+             FF 7E 07 92 01 01 49 00 FF 7C DB C2 7E 05 85 7E 08
+             FF 7E 07 92 01 01 49 00 FF 7C DB C2 7E 05 86 7E 08 FF",
+        )
+        .unwrap();
+        let breakdown = decode(&bytes);
+
+        let expected = "\
+frame 1
+  link
+    address: To(GatewayID(0x1201))
+    type: Type::RECEIVE_REQUEST
+    payload: 00 01 18 83 04
+    crc: 0x4417 (valid)
+  transport
+    packet_number: 6275
+    unknown_1: [0, 1]
+    unknown_2: 4
+frame 2
+  link
+    address: From(GatewayID(0x1201))
+    type: Type::RECEIVE_RESPONSE
+    payload: 00 FF 7C DB C2
+    crc: 0x85A3 (valid)
+  transport
+    rx_buffers_used: None
+    tx_buffers_free: None
+    unknown_a: None
+    unknown_b: None
+    packet_number: 6524
+    slot_counter: SlotCounter(EpochC, SlotNumber(7106))
+    packets: 0
+checksum error
+  link
+    raw: 7E 07 92 01 01 49 00 FF 7C DB C2 7E 05 86 7E 08
+    address: From(GatewayID(0x1201))
+    type: Type::RECEIVE_RESPONSE
+    payload: 00 FF 7C DB C2
+    crc: 0x86A3 (invalid, computed 0x85A3)
+2 frames, 0 runts, 0 giants, 1 checksum errors, 0 noise
+";
+        assert_eq!(breakdown, expected);
+    }
+
+    #[test]
+    fn received_packets() {
+        let mut payload = vec![0x00, 0xFF, 0x85, 0xDB, 0xC2];
+        // Network header: power report from node 9
+        payload.extend_from_slice(&[0x31, 0x00, 0x09, 0x12, 0x34, 0x56, 13]);
+        payload.extend_from_slice(&[0; 13]);
+        // A truncated packet
+        payload.extend_from_slice(&[0x31, 0x00]);
+
+        let frame = link::Frame {
+            address: Address::From(GatewayID::try_from(0x1201).unwrap()),
+            frame_type: Type::RECEIVE_RESPONSE,
+            payload,
+        };
+        let breakdown = decode(&frame.encode());
+
+        assert!(breakdown.contains("    packets: 2\n"), "{}", breakdown);
+        assert!(breakdown.contains("  network\n    packet_type: PacketType::POWER_REPORT\n"));
+        assert!(breakdown.contains("    node_address: NodeAddress(0x0009)\n"));
+        assert!(breakdown.contains("  application\n    power_report: PowerReport {"));
+        assert!(breakdown.contains("  network\n    invalid: PacketTooShortError\n"));
+        assert!(breakdown.ends_with("1 frames, 0 runts, 0 giants, 0 checksum errors, 0 noise\n"));
+    }
+}
@@ -0,0 +1,240 @@
+//! Finding out which sources carry TAP traffic.
+//!
+//! [`probe()`] reads a source for a short while, decoding whatever arrives with the link layer
+//! `Receiver`, and [`Probe::verdict()`] interprets the result. [`rank()`] orders several probes so
+//! that the most promising source comes first.
+
+use crate::config::SourceConfig;
+use crate::gateway::link;
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How many bytes must arrive before `Probe::verdict()` draws conclusions from their content.
+const MIN_BYTES: u64 = 16;
+
+/// The result of reading a source for a while.
+#[derive(Debug)]
+pub struct Probe {
+    pub source: SourceConfig,
+    /// The number of bytes received.
+    pub bytes: u64,
+    /// The number of received bytes which were zero.
+    pub zero_bytes: u64,
+    /// The link layer `Receiver`'s counters after decoding everything received.
+    pub counters: link::Counters,
+    /// The error which ended the probe early, if any.
+    pub error: Option<std::io::Error>,
+}
+
+/// What a `Probe` says about its source.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Verdict {
+    /// Valid frames were received: this source sees a live TAP bus.
+    Live,
+    /// Data was received but mostly zero bytes, which is what a UART reads from an RS-485 line
+    /// idling in the wrong state: the A and B wires are likely swapped.
+    PolaritySwapped,
+    /// Data was received but no valid frames: the baud rate or other line settings are likely
+    /// wrong, or this isn't a TAP bus.
+    WrongLineSettings,
+    /// Nothing, or too little to judge, was received.
+    Silent,
+    /// The source couldn't be opened or read.
+    Failed,
+}
+
+impl Probe {
+    /// The number of frames discarded for any reason, plus line noise.
+    pub fn errors(&self) -> u64 {
+        self.counters.runts + self.counters.giants + self.counters.checksums + self.counters.noise
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        if self.counters.frames > 0 {
+            Verdict::Live
+        } else if self.bytes >= MIN_BYTES && self.zero_bytes * 2 >= self.bytes {
+            Verdict::PolaritySwapped
+        } else if self.bytes >= MIN_BYTES {
+            Verdict::WrongLineSettings
+        } else if self.error.is_some() {
+            Verdict::Failed
+        } else {
+            Verdict::Silent
+        }
+    }
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Verdict::Live => "live TAP bus",
+            Verdict::PolaritySwapped => {
+                "no valid frames, mostly zero bytes: A/B wires likely swapped"
+            }
+            Verdict::WrongLineSettings => "no valid frames: wrong baud rate or line settings?",
+            Verdict::Silent => "silent",
+            Verdict::Failed => "failed",
+        })
+    }
+}
+
+/// Open `source` and decode whatever it receives within `duration`.
+///
+/// The source is opened and read on a thread of its own. If that thread is still blocked opening
+/// or reading the source when `duration` is up, it is abandoned, and exits once its next read
+/// returns.
+pub fn probe(source: SourceConfig, duration: Duration) -> Probe {
+    let deadline = Instant::now() + duration;
+    let (sender, receiver) = mpsc::channel();
+
+    let opened = source.clone();
+    let spawned = std::thread::Builder::new()
+        .name(format!("probe {}", source))
+        .spawn(move || read(opened, sender));
+
+    let mut probe = Probe {
+        source,
+        bytes: 0,
+        zero_bytes: 0,
+        counters: Default::default(),
+        error: spawned.err(),
+    };
+
+    let mut rx = link::Receiver::new(());
+    while probe.error.is_none() {
+        let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+            break;
+        };
+        match receiver.recv_timeout(remaining) {
+            Ok(Ok(bytes)) => {
+                probe.bytes += bytes.len() as u64;
+                probe.zero_bytes += bytes.iter().filter(|b| **b == 0).count() as u64;
+                rx.extend_from_slice(&bytes);
+            }
+            Ok(Err(e)) => probe.error = Some(e),
+            Err(_) => break,
+        }
+    }
+
+    probe.counters = *rx.counters();
+    probe
+}
+
+/// Read `source` until it ends, fails, or the probe is over.
+fn read(source: SourceConfig, sender: mpsc::Sender<std::io::Result<Vec<u8>>>) {
+    let mut conn = match source.open() {
+        Ok(conn) => conn,
+        Err(e) => {
+            sender.send(Err(e)).ok();
+            return;
+        }
+    };
+
+    let mut buffer = [0u8; 1024];
+    loop {
+        let result = match conn.read(&mut buffer) {
+            Ok(0) => return,
+            Ok(n) => Ok(buffer[..n].to_vec()),
+            // Sending nothing notices when the probe is over
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                Ok(Vec::new())
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+
+        let failed = result.is_err();
+        if sender.send(result).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Probe all `sources` at once, returning the probes ranked by `rank()`.
+pub fn probe_all(sources: Vec<SourceConfig>, duration: Duration) -> Vec<Probe> {
+    let threads = sources
+        .into_iter()
+        .map(|source| std::thread::spawn(move || probe(source, duration)))
+        .collect::<Vec<_>>();
+
+    let mut probes = threads
+        .into_iter()
+        .filter_map(|thread| thread.join().ok())
+        .collect::<Vec<_>>();
+    rank(&mut probes);
+    probes
+}
+
+/// Order `probes` by verdict, then by the most valid frames, then by the fewest errors.
+pub fn rank(probes: &mut [Probe]) {
+    probes.sort_by_key(|probe| {
+        (
+            probe.verdict(),
+            std::cmp::Reverse(probe.counters.frames),
+            probe.errors(),
+        )
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{StreamInput, StreamSourceConfig};
+
+    fn file(name: &str, content: &[u8]) -> SourceConfig {
+        let path =
+            std::env::temp_dir().join(format!("taptap-detect-{}-{}.bin", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        SourceConfig::Stream(StreamSourceConfig {
+            input: StreamInput::File(path),
+            follow: false,
+        })
+    }
+
+    #[test]
+    fn verdicts() {
+        let sources = vec![
+            file("zeros", &[0u8; 256]),
+            file("live", crate::test_data::ENUMERATION_SEQUENCE),
+            file("garbage", &[0x55u8; 256]),
+            file("empty", b""),
+        ];
+        let paths = sources.clone();
+
+        let probes = probe_all(sources, Duration::from_secs(5));
+        for source in paths {
+            if let SourceConfig::Stream(StreamSourceConfig {
+                input: StreamInput::File(path),
+                ..
+            }) = source
+            {
+                std::fs::remove_file(path).ok();
+            }
+        }
+
+        let verdicts = probes.iter().map(Probe::verdict).collect::<Vec<_>>();
+        assert_eq!(
+            verdicts,
+            vec![
+                Verdict::Live,
+                Verdict::PolaritySwapped,
+                Verdict::WrongLineSettings,
+                Verdict::Silent,
+            ]
+        );
+        assert!(probes[0].counters.frames > 0);
+        assert_eq!(probes[0].counters.checksums, 0);
+    }
+
+    #[test]
+    fn failed() {
+        let source = SourceConfig::Stream(StreamSourceConfig {
+            input: StreamInput::File("/nonexistent/taptap".into()),
+            follow: false,
+        });
+        let probe = probe(source, Duration::from_secs(5));
+        assert_eq!(probe.verdict(), Verdict::Failed);
+        assert_eq!(probe.error.unwrap().kind(), ErrorKind::NotFound);
+    }
+}
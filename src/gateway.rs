@@ -9,6 +9,6 @@
 pub mod physical;
 
 pub mod link;
-pub use link::{Frame, GatewayID};
+pub use link::{Frame, FrameRef, GatewayID};
 
 pub mod transport;
@@ -1,14 +1,23 @@
 //! The gateway link layer.
 
+use std::time::SystemTime;
+
 mod address;
 
 pub use address::{Address, GatewayID, InvalidGatewayID};
 
 mod crc;
+pub(crate) use crc::crc;
 
 mod escaping;
+pub(crate) use escaping::unescape;
 mod receive;
-pub use receive::{Counters, Receiver, Sink};
+pub use receive::{Counters, ErrorKind, Receiver, Sink};
+
+#[cfg(feature = "transmit")]
+mod transmit;
+#[cfg(feature = "transmit")]
+pub use transmit::{SendError, Sent, TransmitConfig, Transmitter};
 
 /// A gateway link layer frame.
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -41,7 +50,24 @@ impl Frame {
         // Add the start sequence
         output_buffer.extend_from_slice(start);
 
-        // Assemble the middle
+        // Append the escaped content to the output buffer
+        escaping::escape(&self.unescaped(), &mut output_buffer);
+
+        // Append the terminator
+        output_buffer.extend_from_slice(end);
+
+        // Ensure we didn't need to reallocate
+        debug_assert_eq!(output_buffer.capacity(), initial_output_buffer_capacity);
+
+        // Ensure we didn't over-allocate
+        debug_assert!(initial_output_buffer_capacity <= output_buffer.len() + 6);
+
+        output_buffer
+    }
+
+    /// The content of the frame as it is checksummed: the address, frame type, payload and CRC,
+    /// without a preamble, escaping or terminator.
+    pub fn unescaped(&self) -> Vec<u8> {
         let mut body = Vec::with_capacity(2 + 2 + self.payload.len() + 2);
         let initial_body_capacity = body.capacity();
         body.extend_from_slice(&<[u8; 2]>::from(self.address));
@@ -52,21 +78,34 @@ impl Frame {
         let crc = crc::crc(&body);
         body.extend_from_slice(&crc.to_le_bytes());
 
-        // Append the escaped content to the output buffer
-        escaping::escape(&body, &mut output_buffer);
-
-        // Append the terminator
-        output_buffer.extend_from_slice(end);
-
-        // Ensure we didn't need to reallocate
+        // Ensure we neither reallocated nor over-allocated
         debug_assert_eq!(body.capacity(), initial_body_capacity);
-        debug_assert_eq!(output_buffer.capacity(), initial_output_buffer_capacity);
-
-        // Ensure we didn't over-allocate
         debug_assert_eq!(body.len(), initial_body_capacity);
-        debug_assert!(initial_output_buffer_capacity <= output_buffer.len() + 6);
 
-        output_buffer
+        body
+    }
+}
+
+/// A received gateway link layer frame, borrowed from a `Receiver`'s buffer.
+///
+/// Frames are only valid for the duration of `Sink::frame()`. Use `Frame::from()` to keep one,
+/// which drops the timestamp.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FrameRef<'a> {
+    pub address: Address,
+    pub frame_type: Type,
+    pub payload: &'a [u8],
+    /// When the frame was received: the timestamp of the bytes which completed it.
+    pub received: SystemTime,
+}
+
+impl From<FrameRef<'_>> for Frame {
+    fn from(frame: FrameRef<'_>) -> Self {
+        Self {
+            address: frame.address,
+            frame_type: frame.frame_type,
+            payload: frame.payload.to_vec(),
+        }
     }
 }
 
@@ -148,6 +187,20 @@ mod tests {
         assert!(encoded.capacity() <= encoded.len() + 6);
     }
 
+    #[test]
+    fn frame_unescaped() {
+        let frame = Frame {
+            address: Address::From(GatewayID::try_from(0x1201).unwrap()),
+            frame_type: Type(0x0149),
+            payload: b"\x00\xFF\x7C\xDB\xC2".as_slice().into(),
+        };
+
+        assert_eq!(
+            frame.unescaped().as_slice(),
+            [0x92, 0x01, 0x01, 0x49, 0x00, 0xFF, 0x7C, 0xDB, 0xC2, 0xA3, 0x85].as_slice()
+        );
+    }
+
     #[test]
     fn type_debug() {
         assert_eq!(
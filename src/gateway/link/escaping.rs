@@ -36,6 +36,19 @@ pub fn escape(buffer: &[u8], output: &mut Vec<u8>) {
     }
 }
 
+/// Remove link layer escaping.
+pub fn unescape(buffer: &[u8]) -> Result<Vec<u8>, InvalidEscapeSequence> {
+    let mut output = Vec::with_capacity(buffer.len());
+    let mut bytes = buffer.iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            0x7e => output.push(unescaped_byte(*bytes.next().ok_or(InvalidEscapeSequence)?)?),
+            _ => output.push(*byte),
+        }
+    }
+    Ok(output)
+}
+
 pub fn unescaped_byte(byte_after_0x7e: u8) -> Result<u8, InvalidEscapeSequence> {
     match byte_after_0x7e {
         0x00 => Ok(0x7e),
@@ -87,4 +100,13 @@ mod tests {
             assert_eq!(output, escaped, "{:?}", raw);
         }
     }
+
+    #[test]
+    fn test_unescape() {
+        for (raw, escaped) in EXAMPLES.iter().copied() {
+            assert_eq!(unescape(escaped), Ok(raw.to_vec()), "{:?}", escaped);
+        }
+        assert_eq!(unescape(b"\x7e\x08"), Err(InvalidEscapeSequence));
+        assert_eq!(unescape(b"abc\x7e"), Err(InvalidEscapeSequence));
+    }
 }
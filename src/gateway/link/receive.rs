@@ -2,37 +2,92 @@ use super::*;
 
 /// An object which handles reception callbacks.
 pub trait Sink {
-    fn frame(&mut self, frame: Frame);
+    /// Handle a frame, which borrows the `Receiver`'s buffer.
+    fn frame(&mut self, frame: FrameRef<'_>);
+
+    /// Handle a line error, along with the bytes as received which caused it.
+    ///
+    /// Runts and checksum errors include the whole frame, from `7E 07` to `7E 08`, and giants the
+    /// frame up to the byte which made it too long. Noise is reported once it ends, and includes
+    /// any frame it interrupted. At most `2 * 256 + 4` bytes are included.
+    ///
+    /// Errors are counted in the `Receiver`'s `Counters` either way. By default, they are ignored.
+    fn error(&mut self, kind: ErrorKind, raw: &[u8]) {
+        let _ = (kind, raw);
+    }
+}
+
+/// A kind of line error, as counted in `Counters`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ErrorKind {
+    /// A frame too short to be valid.
+    Runt,
+    /// A frame too long to be valid.
+    Giant,
+    /// A frame with an incorrect checksum.
+    Checksum,
+    /// Bytes between frames which aren't a preamble, or an invalid escape sequence within a frame.
+    Noise,
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ErrorKind::Runt => "runt",
+            ErrorKind::Giant => "giant",
+            ErrorKind::Checksum => "checksum",
+            ErrorKind::Noise => "noise",
+        })
+    }
 }
 
+/// Collect owned copies of each frame.
 impl Sink for Vec<Frame> {
-    fn frame(&mut self, frame: Frame) {
-        self.push(frame.clone());
+    fn frame(&mut self, frame: FrameRef<'_>) {
+        self.push(frame.into());
     }
 }
 
-/// A receiver which converts a series of bytes into a series of `Frame`s.
+/// Discard frames, e.g. when only the receiver's `Counters` are of interest.
+impl Sink for () {
+    fn frame(&mut self, _frame: FrameRef<'_>) {}
+}
+
+/// A receiver which converts a series of bytes into a series of `FrameRef`s.
 ///
 /// The receiver tolerates line errors and attempts to re-synchronize whenever possible. Errors are
 /// reported by incrementing counters.
+///
+/// Frames are unescaped into a fixed buffer inside the receiver, so receiving doesn't allocate.
 #[derive(Debug)]
 pub struct Receiver<S: Sink> {
     sink: S,
     state: State,
     counters: Counters,
-    buffer: Vec<u8>,
+    buffer: [u8; MAX_FRAME_SIZE],
+    len: usize,
+    raw: [u8; MAX_RAW_SIZE],
+    raw_len: usize,
+    timestamp: SystemTime,
 }
 
-impl<S: Sink> Receiver<S> {
-    const MAX_FRAME_SIZE: usize = 256;
+const MAX_FRAME_SIZE: usize = 256;
+
+/// Enough for a frame of `MAX_FRAME_SIZE` bytes, all escaped, with its start and end sequences.
+const MAX_RAW_SIZE: usize = 2 * MAX_FRAME_SIZE + 4;
 
+impl<S: Sink> Receiver<S> {
     /// Instantiate a new receiver with a given `Sink`.
     pub fn new(sink: S) -> Self {
         Self {
             sink,
             state: Default::default(),
             counters: Default::default(),
-            buffer: Default::default(),
+            buffer: [0; MAX_FRAME_SIZE],
+            len: 0,
+            raw: [0; MAX_RAW_SIZE],
+            raw_len: 0,
+            timestamp: SystemTime::UNIX_EPOCH,
         }
     }
 
@@ -61,15 +116,77 @@ impl<S: Sink> Receiver<S> {
         self.counters = Counters::default();
     }
 
-    /// Add a slice of bytes to the receiver.
+    /// Add a slice of bytes received just now to the receiver.
     ///
     /// The receiver processes these bytes and calls functions on `Sink`.
-    pub fn extend_from_slice(&mut self, buffer: &[u8]) {
-        for byte in buffer {
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.extend_from_slice_at(bytes, SystemTime::now());
+    }
+
+    /// Add a slice of bytes received at `timestamp` to the receiver, e.g. as returned by a
+    /// `SourceReader` or read from a capture.
+    ///
+    /// Frames completed by these bytes are timestamped with `timestamp`.
+    pub fn extend_from_slice_at(&mut self, mut bytes: &[u8], timestamp: SystemTime) {
+        self.timestamp = timestamp;
+        while !bytes.is_empty() {
+            // Inside a frame, everything up to the next 0x7E is data, and inside a giant,
+            // everything up to the next 0x7E is discarded
+            let consumed = match self.state {
+                State::Frame => self.push_data(bytes),
+                State::Giant => memchr::memchr(0x7e, bytes).unwrap_or(bytes.len()),
+                _ => 0,
+            };
+
+            // Anything else goes through the state machine one byte at a time
+            let consumed = if consumed == 0 {
+                self.push_u8(bytes[0]);
+                1
+            } else {
+                consumed
+            };
+
+            bytes = &bytes[consumed..];
+        }
+    }
+
+    /// Add a slice of bytes received at `timestamp` one byte at a time through the state machine,
+    /// without scanning for frame boundaries in bulk.
+    ///
+    /// This is how the receiver used to decode, kept as a baseline for `benches/receiver.rs`. It
+    /// behaves exactly like `extend_from_slice_at()`, only slower.
+    #[doc(hidden)]
+    pub fn extend_from_slice_per_byte(&mut self, bytes: &[u8], timestamp: SystemTime) {
+        self.timestamp = timestamp;
+        for byte in bytes {
             self.push_u8(*byte);
         }
     }
 
+    /// Copy the data bytes at the start of `bytes` into the buffer, as far as it has room,
+    /// returning how many were copied.
+    fn push_data(&mut self, bytes: &[u8]) -> usize {
+        let data = memchr::memchr(0x7e, bytes).unwrap_or(bytes.len());
+        let n = data.min(MAX_FRAME_SIZE - self.len);
+        self.buffer[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+        self.record(&bytes[..n]);
+        n
+    }
+
+    /// Keep `bytes` as received, in case they turn out to be an error.
+    fn record(&mut self, bytes: &[u8]) {
+        let n = bytes.len().min(MAX_RAW_SIZE - self.raw_len);
+        self.raw[self.raw_len..self.raw_len + n].copy_from_slice(&bytes[..n]);
+        self.raw_len += n;
+    }
+
+    /// Report an error to the `Sink` with the bytes recorded, and start recording afresh.
+    fn error(&mut self, kind: ErrorKind) {
+        self.sink.error(kind, &self.raw[..self.raw_len]);
+        self.raw_len = 0;
+    }
+
     /// Add a single byte to the receiver.
     fn push_u8(&mut self, byte: u8) {
         let next_state = match self.state {
@@ -104,8 +221,9 @@ impl<S: Sink> Receiver<S> {
                     // Escape sequence
                     0x7e => State::FrameEscape,
                     // Normal data byte
-                    _ if self.buffer.len() < Self::MAX_FRAME_SIZE => {
-                        self.buffer.push(byte);
+                    _ if self.len < MAX_FRAME_SIZE => {
+                        self.buffer[self.len] = byte;
+                        self.len += 1;
                         State::Frame
                     }
                     // Overlong frame
@@ -115,19 +233,18 @@ impl<S: Sink> Receiver<S> {
             State::FrameEscape => {
                 if byte == 0x08 {
                     // End of frame
-                    self.parse_frame_from_buffer();
-                    self.buffer.truncate(0);
                     State::Idle
                 } else if let Ok(byte) = escaping::unescaped_byte(byte) {
-                    if self.buffer.len() < Self::MAX_FRAME_SIZE {
-                        self.buffer.push(byte);
+                    if self.len < MAX_FRAME_SIZE {
+                        self.buffer[self.len] = byte;
+                        self.len += 1;
                         State::Frame
                     } else {
-                        self.buffer.truncate(0);
+                        self.len = 0;
                         State::GiantEscape
                     }
                 } else {
-                    self.buffer.truncate(0);
+                    self.len = 0;
                     State::Noise
                 }
             }
@@ -147,13 +264,33 @@ impl<S: Sink> Receiver<S> {
             }
         };
 
+        if self.state == State::Noise && next_state != State::Noise {
+            self.error(ErrorKind::Noise);
+        }
+
+        // Record everything from the start of a frame or of noise, up to the end of the frame or the
+        // point where it became a giant
+        match next_state {
+            State::Idle if self.state == State::FrameEscape => self.record(&[byte]),
+            State::Idle => {}
+            State::Giant | State::GiantEscape if self.state.is_giant() => {}
+            State::Frame if self.state == State::GiantEscape => self.record(&[0x7e, byte]),
+            _ => self.record(&[byte]),
+        }
+
         match next_state {
+            State::Idle if self.state == State::FrameEscape => {
+                self.parse_frame_from_buffer();
+                self.len = 0;
+                self.raw_len = 0;
+            }
             State::Noise if self.state != State::Noise => {
                 self.counters.noise += 1;
             }
-            State::Giant if self.state != State::Giant && self.state != State::GiantEscape => {
-                self.buffer.truncate(0);
+            State::Giant | State::GiantEscape if !self.state.is_giant() => {
+                self.len = 0;
                 self.counters.giants += 1;
+                self.error(ErrorKind::Giant);
             }
             _ => {}
         }
@@ -162,18 +299,22 @@ impl<S: Sink> Receiver<S> {
     }
 
     fn parse_frame_from_buffer(&mut self) {
+        let buffer = &self.buffer[..self.len];
+
         // Ensure we're a valid length
-        if self.buffer.len() < 6 {
+        if buffer.len() < 6 {
             self.counters.runts += 1;
+            self.error(ErrorKind::Runt);
             return;
         }
 
         // Verify the CRC
-        let (body, expected_crc) = self.buffer.split_at(self.buffer.len() - 2);
+        let (body, expected_crc) = buffer.split_at(buffer.len() - 2);
         let crc = crc::crc(body);
         let expected_crc = u16::from_le_bytes([expected_crc[0], expected_crc[1]]);
         if expected_crc != crc {
             self.counters.checksums += 1;
+            self.error(ErrorKind::Checksum);
             return;
         }
 
@@ -181,10 +322,11 @@ impl<S: Sink> Receiver<S> {
         let frame_type = Type(u16::from_be_bytes([body[2], body[3]]));
 
         self.counters.frames += 1;
-        self.sink.frame(Frame {
+        self.sink.frame(FrameRef {
             address,
             frame_type,
-            payload: Vec::from(body.split_at(4).1),
+            payload: &body[4..],
+            received: self.timestamp,
         });
     }
 }
@@ -201,6 +343,12 @@ enum State {
     GiantEscape,
 }
 
+impl State {
+    fn is_giant(self) -> bool {
+        matches!(self, State::Giant | State::GiantEscape)
+    }
+}
+
 /// Counters describing the internal state transitions of a `Receiver`.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub struct Counters {
@@ -220,6 +368,23 @@ pub struct Counters {
 mod tests {
     use super::*;
 
+    /// A sink keeping both frames and errors.
+    #[derive(Debug, Default, Eq, PartialEq)]
+    struct Log {
+        frames: Vec<Frame>,
+        errors: Vec<(ErrorKind, Vec<u8>)>,
+    }
+
+    impl Sink for Log {
+        fn frame(&mut self, frame: FrameRef<'_>) {
+            self.frames.push(frame.into());
+        }
+
+        fn error(&mut self, kind: ErrorKind, raw: &[u8]) {
+            self.errors.push((kind, raw.to_vec()));
+        }
+    }
+
     #[test]
     fn happy_path() {
         let mut rx = Receiver::new(Vec::new());
@@ -242,7 +407,7 @@ mod tests {
                 noise: 0,
             }
         );
-        assert_eq!(rx.buffer.len(), 0);
+        assert_eq!(rx.len, 0);
 
         assert_eq!(
             rx.sink,
@@ -297,7 +462,7 @@ mod tests {
                 noise: 3,
             }
         );
-        assert_eq!(rx.buffer.len(), 0);
+        assert_eq!(rx.len, 0);
     }
 
     #[test]
@@ -322,7 +487,7 @@ mod tests {
                 noise: 0,
             }
         );
-        assert_eq!(rx.buffer.len(), 0);
+        assert_eq!(rx.len, 0);
     }
 
     #[test]
@@ -347,7 +512,7 @@ mod tests {
                 noise: 6,
             }
         );
-        assert_eq!(rx.buffer.len(), 0);
+        assert_eq!(rx.len, 0);
     }
 
     #[test]
@@ -376,7 +541,7 @@ mod tests {
                 noise: 0,
             }
         );
-        assert_eq!(rx.buffer.len(), 0);
+        assert_eq!(rx.len, 0);
     }
 
     #[test]
@@ -399,6 +564,98 @@ mod tests {
                 noise: 0,
             }
         );
-        assert_eq!(rx.buffer.len(), 0);
+        assert_eq!(rx.len, 0);
+    }
+
+    #[test]
+    fn chunking() {
+        // Frames, noise and a giant, received all at once
+        let mut bytes = crate::test_data::ENUMERATION_SEQUENCE.to_vec();
+        bytes.extend_from_slice(&[0xee, 0x7E, 0x07, 0x12, 0x01]);
+        bytes.extend_from_slice(&[0x55; 300]);
+        bytes.extend_from_slice(&[0x7E, 0x08]);
+        bytes.extend_from_slice(crate::test_data::ENUMERATION_SEQUENCE);
+
+        let mut expected = Receiver::new(Log::default());
+        expected.extend_from_slice(&bytes);
+        assert_eq!(expected.counters.giants, 1);
+        assert_eq!(expected.counters.noise, 1);
+
+        // Receiving the same bytes in chunks of any size makes no difference
+        for size in 1..=bytes.len() {
+            let mut rx = Receiver::new(Log::default());
+            for chunk in bytes.chunks(size) {
+                rx.extend_from_slice(chunk);
+            }
+            assert_eq!(rx.counters, expected.counters, "chunks of {}", size);
+            assert_eq!(rx.sink, expected.sink, "chunks of {}", size);
+        }
+
+        // Nor does stepping the state machine for every byte
+        let mut rx = Receiver::new(Log::default());
+        rx.extend_from_slice_per_byte(&bytes, SystemTime::now());
+        assert_eq!(rx.counters, expected.counters);
+        assert_eq!(rx.sink, expected.sink);
+    }
+
+    #[test]
+    fn errors() {
+        let mut rx = Receiver::new(Log::default());
+        rx.extend_from_slice(&[
+            // noise, ended by a preamble
+            0xee, 0x12, 0x00, 0xFF, // runt
+            0x7E, 0x07, 0x00, 0x7E, 0x08, 0xFF, // checksum, with an escape sequence
+            0x7E, 0x07, 0x92, 0x01, 0x01, 0x49, 0x00, 0xFF, 0x7C, 0xDB, 0xC2, 0x7E, 0x05, 0x86,
+            0x7E, 0x08, 0xFF,
+            // a frame interrupted by an invalid escape sequence, then noise up to the next frame
+            0x7E, 0x07, 0x12, 0x01, 0x7E, 0x7E, 0x33, // a valid frame
+            0x7E, 0x07, 0x00, 0x01, 0x00, 0x00, 0x89, 0xD0, 0x7E, 0x08,
+        ]);
+        assert_eq!(rx.counters.frames, 1);
+        assert_eq!(rx.sink.frames.len(), 1);
+        assert_eq!(
+            rx.sink.errors,
+            vec![
+                (ErrorKind::Noise, vec![0xee, 0x12]),
+                (ErrorKind::Runt, vec![0x7E, 0x07, 0x00, 0x7E, 0x08]),
+                (
+                    ErrorKind::Checksum,
+                    vec![
+                        0x7E, 0x07, 0x92, 0x01, 0x01, 0x49, 0x00, 0xFF, 0x7C, 0xDB, 0xC2, 0x7E,
+                        0x05, 0x86, 0x7E, 0x08,
+                    ]
+                ),
+                (
+                    ErrorKind::Noise,
+                    vec![0x7E, 0x07, 0x12, 0x01, 0x7E, 0x7E, 0x33]
+                ),
+            ]
+        );
+        assert_eq!(rx.raw_len, 0);
+    }
+
+    #[test]
+    fn giant_errors() {
+        let mut rx = Receiver::new(Log::default());
+        rx.extend_from_slice(&[0x00, 0xFF, 0xFF, 0x7E, 0x07]);
+        rx.extend_from_slice(&[0x55; MAX_FRAME_SIZE + 10]);
+        rx.extend_from_slice(&[0x7E, 0x08]);
+
+        // A giant whose first excess byte is escaped
+        rx.extend_from_slice(&[0xFF, 0x7E, 0x07]);
+        rx.extend_from_slice(&[0x55; MAX_FRAME_SIZE]);
+        rx.extend_from_slice(&[0x7E, 0x05, 0x55, 0x7E, 0x08]);
+
+        assert_eq!(rx.counters.giants, 2);
+        let errors = &rx.sink.errors;
+        assert_eq!(errors.len(), 2);
+
+        // Giants are reported up to the first excess byte
+        assert_eq!(errors[0].0, ErrorKind::Giant);
+        assert_eq!(errors[0].1.len(), 2 + MAX_FRAME_SIZE + 1);
+        assert_eq!(&errors[0].1[..2], &[0x7E, 0x07]);
+        assert_eq!(errors[1].0, ErrorKind::Giant);
+        assert_eq!(errors[1].1.len(), 2 + MAX_FRAME_SIZE + 2);
+        assert_eq!(&errors[1].1[MAX_FRAME_SIZE + 2..], &[0x7E, 0x05]);
     }
 }
@@ -0,0 +1,317 @@
+use super::Frame;
+use crate::gateway::physical::ReadWriteConnection;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+/// How a `Transmitter` shares the bus.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TransmitConfig {
+    /// How long the bus must be quiet before it is considered idle.
+    pub idle_gap: Duration,
+    /// How long to wait for the bus to become idle before giving up.
+    pub idle_timeout: Duration,
+    /// How long to wait for the echo of a transmitted frame, or `None` for connections which don't
+    /// receive what they send.
+    pub echo_timeout: Option<Duration>,
+}
+
+impl Default for TransmitConfig {
+    fn default() -> Self {
+        Self {
+            // About a dozen bytes at 38400 baud
+            idle_gap: Duration::from_millis(3),
+            idle_timeout: Duration::from_secs(1),
+            echo_timeout: Some(Duration::from_millis(100)),
+        }
+    }
+}
+
+/// An error sending a frame.
+#[derive(thiserror::Error, Debug)]
+pub enum SendError {
+    #[error("bus busy for {0:?}")]
+    BusBusy(Duration),
+    #[error("collision: sent {sent:02X?}, received {received:02X?}")]
+    Collision { sent: Vec<u8>, received: Vec<u8> },
+    #[error("no echo after {timeout:?}, {received} of {sent} bytes received")]
+    NoEcho {
+        timeout: Duration,
+        sent: usize,
+        received: usize,
+    },
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The result of sending a frame successfully.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Sent {
+    /// How long it took for the bus to become idle.
+    pub waited: Duration,
+    /// The number of bytes transmitted, including the preamble and terminator.
+    pub bytes: usize,
+    /// Whether the transmission was read back intact.
+    pub echoed: bool,
+}
+
+/// A transmitter which sends `Frame`s over a connection shared with other devices on the bus.
+///
+/// Before sending, the transmitter waits for the bus to be idle. Afterwards, unless the connection
+/// doesn't receive its own transmissions, it reads the echo back to detect collisions.
+///
+/// Everything read from the connection, including echoes, is passed to the `received` callback,
+/// which would typically feed a `Receiver`. While not sending, `receive()` keeps reading the bus.
+#[derive(Debug)]
+pub struct Transmitter<C: ReadWriteConnection, F = fn(&[u8])> {
+    conn: C,
+    config: TransmitConfig,
+    received: F,
+    buffer: Vec<u8>,
+}
+
+impl<C: ReadWriteConnection> Transmitter<C> {
+    pub fn new(conn: C, config: TransmitConfig) -> std::io::Result<Self> {
+        Self::with_received(conn, config, |_| {})
+    }
+}
+
+impl<C: ReadWriteConnection, F: FnMut(&[u8])> Transmitter<C, F> {
+    /// Transmit over `conn`, passing everything read from the bus to `received`.
+    ///
+    /// Fails if `conn` doesn't support read timeouts, which are needed to notice an idle bus.
+    pub fn with_received(
+        mut conn: C,
+        config: TransmitConfig,
+        received: F,
+    ) -> std::io::Result<Self> {
+        conn.set_read_timeout(config.idle_gap)?;
+        Ok(Self {
+            conn,
+            config,
+            received,
+            buffer: vec![0; 1024],
+        })
+    }
+
+    pub fn config(&self) -> &TransmitConfig {
+        &self.config
+    }
+
+    /// Destroy the transmitter to obtain the connection.
+    pub fn into_inner(self) -> C {
+        self.conn
+    }
+
+    /// Read from the bus once, returning the number of bytes received, or `Ok(0)` if the bus was
+    /// idle for the idle gap.
+    pub fn receive(&mut self) -> std::io::Result<usize> {
+        loop {
+            match self.conn.read(&mut self.buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    (self.received)(&self.buffer[..n]);
+                    return Ok(n);
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                    return Ok(0)
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Wait for the bus to be idle, send `frame`, and check its echo.
+    pub fn send(&mut self, frame: &Frame) -> Result<Sent, SendError> {
+        let waited = self.wait_for_idle()?;
+
+        let bytes = frame.encode();
+        self.conn.write_all(&bytes)?;
+        self.conn.flush()?;
+
+        let echoed = match self.config.echo_timeout {
+            Some(timeout) => {
+                self.check_echo(&bytes, timeout)?;
+                true
+            }
+            None => false,
+        };
+
+        Ok(Sent {
+            waited,
+            bytes: bytes.len(),
+            echoed,
+        })
+    }
+
+    /// Read until nothing has arrived for the idle gap, returning how long that took.
+    fn wait_for_idle(&mut self) -> Result<Duration, SendError> {
+        let start = Instant::now();
+        while self.receive()? > 0 {
+            if start.elapsed() >= self.config.idle_timeout {
+                return Err(SendError::BusBusy(start.elapsed()));
+            }
+        }
+        Ok(start.elapsed())
+    }
+
+    /// Read back what was just sent, failing if something else arrives instead.
+    fn check_echo(&mut self, sent: &[u8], timeout: Duration) -> Result<(), SendError> {
+        let start = Instant::now();
+        let mut echo = Vec::with_capacity(sent.len());
+        while echo.len() < sent.len() {
+            if start.elapsed() >= timeout {
+                return Err(SendError::NoEcho {
+                    timeout,
+                    sent: sent.len(),
+                    received: echo.len(),
+                });
+            }
+
+            let n = self.receive()?;
+            let wanted = (sent.len() - echo.len()).min(n);
+            echo.extend_from_slice(&self.buffer[..wanted]);
+
+            if !sent.starts_with(&echo) {
+                return Err(SendError::Collision {
+                    sent: sent.to_vec(),
+                    received: echo,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TcpKeepaliveConfig;
+    use crate::gateway::link::{Address, GatewayID, Type};
+    use crate::gateway::physical::tcp;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    fn frame() -> Frame {
+        Frame {
+            address: Address::To(GatewayID::try_from(0x1201).unwrap()),
+            frame_type: Type::PING_REQUEST,
+            payload: vec![0x7e, 0x01, 0x02],
+        }
+    }
+
+    /// Connect to a stand-in for the bus, which is handled by `bus` on its own thread.
+    fn connect<B>(bus: B) -> tcp::Connection
+    where
+        B: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || bus(listener.accept().unwrap().0));
+
+        let keepalive = TcpKeepaliveConfig {
+            idle: Duration::from_secs(30),
+            interval: Duration::from_secs(10),
+            count: 5,
+        };
+        tcp::Connection::connect(addr, false, keepalive).unwrap()
+    }
+
+    /// A bus which echoes everything sent on it, after some traffic of its own.
+    fn echo(mut stream: TcpStream) {
+        stream.write_all(b"\x00\xff\x7e\x07").unwrap();
+        let mut buffer = [0u8; 64];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => stream.write_all(&buffer[..n]).unwrap(),
+            }
+        }
+    }
+
+    #[test]
+    fn send() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let config = TransmitConfig {
+            idle_gap: Duration::from_millis(50),
+            echo_timeout: Some(Duration::from_secs(5)),
+            ..TransmitConfig::default()
+        };
+        let mut tx = Transmitter::with_received(connect(echo), config, {
+            let received = received.clone();
+            move |bytes: &[u8]| received.lock().unwrap().extend_from_slice(bytes)
+        })
+        .unwrap();
+
+        let sent = tx.send(&frame()).unwrap();
+        assert!(sent.echoed);
+        assert_eq!(sent.bytes, frame().encode().len());
+
+        // The traffic before the frame, and its echo, were both received
+        let mut expected = b"\x00\xff\x7e\x07".to_vec();
+        expected.extend_from_slice(&frame().encode());
+        assert_eq!(*received.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn collision() {
+        let config = TransmitConfig {
+            idle_gap: Duration::from_millis(50),
+            echo_timeout: Some(Duration::from_secs(5)),
+            ..TransmitConfig::default()
+        };
+        let conn = connect(|mut stream| {
+            let mut buffer = [0u8; 64];
+            while let Ok(n @ 1..) = stream.read(&mut buffer) {
+                // Garble the echo, as if another device transmitted at the same time
+                buffer[n / 2] ^= 0x55;
+                stream.write_all(&buffer[..n]).unwrap();
+            }
+        });
+        let mut tx = Transmitter::new(conn, config).unwrap();
+
+        let error = tx.send(&frame()).unwrap_err();
+        assert!(matches!(error, SendError::Collision { .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn bus_busy() {
+        let config = TransmitConfig {
+            idle_gap: Duration::from_millis(50),
+            idle_timeout: Duration::from_millis(200),
+            echo_timeout: None,
+        };
+        let conn = connect(|mut stream| {
+            // Chatter without pause
+            while stream.write_all(b"\x00").is_ok() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+        let mut tx = Transmitter::new(conn, config).unwrap();
+
+        let error = tx.send(&frame()).unwrap_err();
+        assert!(matches!(error, SendError::BusBusy(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn no_echo() {
+        let config = TransmitConfig {
+            idle_gap: Duration::from_millis(20),
+            echo_timeout: Some(Duration::from_millis(100)),
+            ..TransmitConfig::default()
+        };
+        let conn = connect(|mut stream| {
+            let mut buffer = [0u8; 64];
+            while let Ok(1..) = stream.read(&mut buffer) {}
+        });
+        let mut tx = Transmitter::new(conn, config).unwrap();
+
+        let error = tx.send(&frame()).unwrap_err();
+        assert!(
+            matches!(error, SendError::NoEcho { received: 0, .. }),
+            "{:?}",
+            error
+        );
+    }
+}
@@ -6,17 +6,132 @@
 //! * `serialport`, when compiled with the `serialport` feature
 //! * [`tcp`]
 //! * `termios`, when compiled on UNIX-like systems
+//! * [`stream`], reading standard input, files or named pipes
+//! * `trace_meshdcd`, when compiled on Linux with the `trace_meshdcd` feature, tracing the
+//!   controller's own serial port
+//!
+//! With the `async` feature, `tcp` and `termios` also provide tokio connections, and `threaded`
+//! adapts any blocking connection.
+//!
+//! A [`Connection`] can only be read. Writing needs a `ReadWriteConnection`, which exists only
+//! with the `transmit` feature, so builds without it can't transmit on the bus:
+//!
+//! ```compile_fail
+//! use std::io::Write;
+//! use taptap::gateway::physical::Connection;
+//!
+//! fn transmit(conn: &mut dyn Connection) {
+//!     conn.write_all(b"\x00\xff\xff\x7e\x07").unwrap();
+//! }
+//! ```
+//!
+//! As a further check, [`bytes_written()`] counts every byte written to any connection.
 
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A connection which receives what is sent on the bus.
+pub trait Connection: std::io::Read + Debug {
+    /// Make reads fail with `TimedOut` or `WouldBlock` once no data has arrived for `timeout`.
+    ///
+    /// This is needed to notice when the bus goes quiet, e.g. before transmitting. Connections
+    /// which can't time out return `Unsupported`.
+    fn set_read_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        let _ = timeout;
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl<C: Connection + ?Sized> Connection for Box<C> {
+    fn set_read_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+}
+
+/// A connection which can also transmit on the bus.
+#[cfg(feature = "transmit")]
+pub trait ReadWriteConnection: Connection + std::io::Write {}
+
+#[cfg(feature = "transmit")]
+impl<C: ReadWriteConnection + ?Sized> ReadWriteConnection for Box<C> {}
+
+static BYTES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+/// The number of bytes this process has written to any connection.
+///
+/// Without the `transmit` feature, nothing can write to a connection, and this is always zero.
+pub fn bytes_written() -> u64 {
+    BYTES_WRITTEN.load(Ordering::Relaxed)
+}
+
+/// Count bytes written by a `ReadWriteConnection`.
+#[cfg(feature = "transmit")]
+fn count_written(n: usize) {
+    BYTES_WRITTEN.fetch_add(n as u64, Ordering::Relaxed);
+}
 
-pub trait Connection: std::io::Read + std::io::Write + Debug {}
+/// A connection for use with tokio, which like `Connection` can only be read.
+#[cfg(feature = "async")]
+pub trait AsyncConnection: tokio::io::AsyncRead + Debug + Send + Unpin {}
 
+#[cfg(feature = "async")]
+pub mod threaded;
+
+#[cfg(feature = "serialport")]
 pub mod serialport;
 
 #[cfg(unix)]
 pub mod termios;
 
+#[cfg(unix)]
+mod serial_line;
+
 pub mod tcp;
 
-//#[cfg(all(target_arch = "armv7l", target_os = "linux"))]
-//pub mod trace_meshdcd;
+pub mod stream;
+
+#[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+pub mod trace_meshdcd;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TcpKeepaliveConfig;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    const KEEPALIVE: TcpKeepaliveConfig = TcpKeepaliveConfig {
+        idle: Duration::from_secs(30),
+        interval: Duration::from_secs(10),
+        count: 5,
+    };
+
+    #[test]
+    fn reading_writes_nothing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conn =
+            tcp::Connection::connect(listener.local_addr().unwrap(), false, KEEPALIVE).unwrap();
+        let (mut bus, _) = listener.accept().unwrap();
+
+        bus.write_all(b"\x00\xff\x7e\x07").unwrap();
+        let mut buffer = [0u8; 4];
+        conn.read_exact(&mut buffer).unwrap();
+
+        // Other tests may transmit when they can
+        #[cfg(not(feature = "transmit"))]
+        assert_eq!(bytes_written(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "transmit")]
+    fn writes_are_counted() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conn =
+            tcp::Connection::connect(listener.local_addr().unwrap(), false, KEEPALIVE).unwrap();
+
+        let before = bytes_written();
+        conn.write_all(b"\x00\xff\xff\x7e\x07").unwrap();
+        assert!(bytes_written() >= before + 5);
+    }
+}
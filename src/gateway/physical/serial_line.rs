@@ -0,0 +1,118 @@
+//! Serial line settings which `termios` doesn't cover, applied using Linux `ioctl()`s.
+
+use crate::config::{Rs485Config, SerialLineConfig};
+use libc::c_int;
+use std::io::Error;
+
+/// Apply the low latency and RS-485 settings of `line` to the serial port `fd`.
+pub(super) fn configure(fd: c_int, line: &SerialLineConfig) -> Result<(), Error> {
+    if line.low_latency {
+        // Not every driver supports this, and it's only an optimization
+        if let Err(e) = set_low_latency(fd) {
+            log::warn!("unable to enable low latency mode: {}", e);
+        }
+    }
+
+    if let Some(rs485) = &line.rs485 {
+        set_rs485(fd, rs485)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_low_latency(fd: c_int) -> Result<(), Error> {
+    let mut serial: linux::serial_struct = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGSERIAL, &mut serial as *mut _) } != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    serial.flags |= linux::ASYNC_LOW_LATENCY;
+    if unsafe { libc::ioctl(fd, libc::TIOCSSERIAL, &serial as *const _) } != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_rs485(fd: c_int, config: &Rs485Config) -> Result<(), Error> {
+    let mut flags = linux::SER_RS485_ENABLED;
+    if config.rts_active_low {
+        flags |= linux::SER_RS485_RTS_AFTER_SEND;
+    } else {
+        flags |= linux::SER_RS485_RTS_ON_SEND;
+    }
+    if config.rx_during_tx {
+        flags |= linux::SER_RS485_RX_DURING_TX;
+    }
+
+    let rs485 = linux::serial_rs485 {
+        flags,
+        delay_rts_before_send: config.delay_rts_before_send,
+        delay_rts_after_send: config.delay_rts_after_send,
+        padding: [0; 5],
+    };
+    if unsafe { libc::ioctl(fd, libc::TIOCSRS485, &rs485 as *const _) } != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_low_latency(_fd: c_int) -> Result<(), Error> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_rs485(_fd: c_int, _config: &Rs485Config) -> Result<(), Error> {
+    Err(Error::new(
+        std::io::ErrorKind::Unsupported,
+        "RS-485 mode is only supported on Linux",
+    ))
+}
+
+/// Definitions from `<linux/serial.h>`, which `libc` doesn't provide.
+#[cfg(target_os = "linux")]
+#[allow(non_camel_case_types)]
+mod linux {
+    use libc::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort};
+
+    pub const ASYNC_LOW_LATENCY: c_int = 1 << 13;
+
+    pub const SER_RS485_ENABLED: u32 = 1 << 0;
+    pub const SER_RS485_RTS_ON_SEND: u32 = 1 << 1;
+    pub const SER_RS485_RTS_AFTER_SEND: u32 = 1 << 2;
+    pub const SER_RS485_RX_DURING_TX: u32 = 1 << 4;
+
+    #[repr(C)]
+    pub struct serial_struct {
+        pub type_: c_int,
+        pub line: c_int,
+        pub port: c_uint,
+        pub irq: c_int,
+        pub flags: c_int,
+        pub xmit_fifo_size: c_int,
+        pub custom_divisor: c_int,
+        pub baud_base: c_int,
+        pub close_delay: c_ushort,
+        pub io_type: c_char,
+        pub reserved_char: [c_char; 1],
+        pub hub6: c_int,
+        pub closing_wait: c_ushort,
+        pub closing_wait2: c_ushort,
+        pub iomem_base: *mut c_uchar,
+        pub iomem_reg_shift: c_ushort,
+        pub port_high: c_uint,
+        pub iomap_base: c_ulong,
+    }
+
+    #[repr(C)]
+    pub struct serial_rs485 {
+        pub flags: u32,
+        pub delay_rts_before_send: u32,
+        pub delay_rts_after_send: u32,
+        pub padding: [u32; 5],
+    }
+}
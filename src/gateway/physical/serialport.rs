@@ -1,3 +1,4 @@
+use crate::config::{self, SerialLineConfig};
 use serialport::Result;
 use serialport::{
     available_ports, DataBits, FlowControl, Parity, SerialPort, SerialPortInfo, SerialPortType,
@@ -13,8 +14,8 @@ impl PortInfo {
         available_ports().map(|vec| vec.into_iter().map(Self).collect())
     }
 
-    pub fn open(&self) -> Result<Port> {
-        Port::open(&self.0.port_name)
+    pub fn open(&self, line: &SerialLineConfig) -> Result<Port> {
+        Port::open(&self.0.port_name, line)
     }
 
     pub fn name(&self) -> &str {
@@ -28,23 +29,52 @@ impl PortInfo {
 
 #[derive(Debug)]
 pub struct Port {
-    pub inner: Box<dyn SerialPort>,
+    inner: Box<dyn SerialPort>,
+    timeouts: bool,
 }
 
 impl Port {
-    pub fn open(name: &str) -> Result<Self> {
-        serialport::new(name, 38400)
+    pub fn open(name: &str, line: &SerialLineConfig) -> Result<Self> {
+        let builder = serialport::new(name, line.baud_rate)
             .data_bits(DataBits::Eight)
-            .parity(Parity::None)
-            .stop_bits(StopBits::One)
+            .parity(match line.parity {
+                config::Parity::None => Parity::None,
+                config::Parity::Odd => Parity::Odd,
+                config::Parity::Even => Parity::Even,
+            })
+            .stop_bits(match line.stop_bits {
+                config::StopBits::One => StopBits::One,
+                config::StopBits::Two => StopBits::Two,
+            })
             .flow_control(FlowControl::None)
-            .timeout(Duration::from_millis(5))
-            .open()
-            .map(Port::new)
+            .timeout(Duration::from_millis(line.read_timeout));
+
+        // Low latency and RS-485 modes need the file descriptor
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            let port = builder.open_native()?;
+            super::serial_line::configure(port.as_raw_fd(), line)?;
+            Ok(Port::new(Box::new(port)))
+        }
+
+        #[cfg(not(unix))]
+        {
+            if line.rs485.is_some() {
+                return Err(serialport::Error::new(
+                    serialport::ErrorKind::Io(std::io::ErrorKind::Unsupported),
+                    "RS-485 mode is only supported on Linux",
+                ));
+            }
+            builder.open().map(Port::new)
+        }
     }
 
     fn new(inner: Box<dyn SerialPort>) -> Self {
-        Port { inner }
+        Port {
+            inner,
+            timeouts: false,
+        }
     }
 }
 
@@ -53,7 +83,7 @@ impl std::io::Read for Port {
         loop {
             match self.inner.read(buf) {
                 Ok(n) => return Ok(n),
-                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut && !self.timeouts => {
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -62,9 +92,15 @@ impl std::io::Read for Port {
     }
 }
 
+#[cfg(feature = "transmit")]
+impl super::ReadWriteConnection for Port {}
+
+#[cfg(feature = "transmit")]
 impl std::io::Write for Port {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.inner.write(buf)
+        let n = self.inner.write(buf)?;
+        super::count_written(n);
+        Ok(n)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
@@ -72,4 +108,10 @@ impl std::io::Write for Port {
     }
 }
 
-impl super::Connection for Port {}
+impl super::Connection for Port {
+    fn set_read_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.inner.set_timeout(timeout)?;
+        self.timeouts = true;
+        Ok(())
+    }
+}
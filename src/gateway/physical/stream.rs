@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::{Read, Stdin};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long to wait before reading again after reaching the end of a followed input.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A read-only stream of raw bus bytes, read from standard input, a file or a named pipe.
+///
+/// Streams that are not followed report the end of the input as a zero-length read. Followed
+/// streams instead wait for more data to be appended, like `tail -f`, or for a new writer to open
+/// a named pipe.
+#[derive(Debug)]
+pub struct Stream {
+    inner: Inner,
+    follow: bool,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Stdin(Stdin),
+    File(File),
+}
+
+impl Stream {
+    /// Read from standard input.
+    pub fn stdin(follow: bool) -> Self {
+        Self {
+            inner: Inner::Stdin(std::io::stdin()),
+            follow,
+        }
+    }
+
+    /// Read from a file or a named pipe.
+    ///
+    /// Opening a named pipe blocks until another process opens it for writing.
+    pub fn open<P: AsRef<Path>>(path: P, follow: bool) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            inner: Inner::File(File::open(path)?),
+            follow,
+        })
+    }
+}
+
+impl super::Connection for Stream {}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let n = match &mut self.inner {
+                Inner::Stdin(stdin) => stdin.read(buf)?,
+                Inner::File(file) => file.read(buf)?,
+            };
+
+            if n > 0 || !self.follow || buf.is_empty() {
+                return Ok(n);
+            }
+
+            // End of input, but we're following
+            std::thread::sleep(FOLLOW_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn file() {
+        let path = std::env::temp_dir().join(format!("taptap-stream-{}", std::process::id()));
+        std::fs::write(&path, b"\x00\xff\x7e\x07").unwrap();
+
+        let mut stream = Stream::open(&path, false).unwrap();
+        let mut buffer = Vec::new();
+        stream.read_to_end(&mut buffer).unwrap();
+        assert_eq!(buffer, b"\x00\xff\x7e\x07");
+
+        // A followed file picks up appended data
+        let mut stream = Stream::open(&path, true).unwrap();
+        let mut buffer = [0u8; 16];
+        assert_eq!(stream.read(&mut buffer).unwrap(), 4);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"\x7e\x08")
+            .unwrap();
+        assert_eq!(stream.read(&mut buffer).unwrap(), 2);
+        assert_eq!(&buffer[..2], b"\x7e\x08");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
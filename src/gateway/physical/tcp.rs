@@ -1,11 +1,24 @@
 use crate::config::TcpKeepaliveConfig;
-use std::io::{Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::io::Read;
+#[cfg(feature = "transmit")]
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long a read waits for data before reporting a timeout, so that callers can notice an idle
+/// connection.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "async")]
+pub use nonblocking::{AsyncConnection, AsyncListener};
 
 /// A TCP serial connection.
 #[derive(Debug)]
 pub struct Connection {
     socket: TcpStream,
+    #[cfg_attr(not(feature = "transmit"), allow(dead_code))]
     readonly: bool,
 }
 
@@ -16,17 +29,66 @@ impl Connection {
         keepalive: TcpKeepaliveConfig,
     ) -> Result<Self, std::io::Error> {
         let socket = TcpStream::connect(addr)?;
+        Self::new(socket, readonly, keepalive)
+    }
+
+    fn new(
+        socket: TcpStream,
+        readonly: bool,
+        keepalive: TcpKeepaliveConfig,
+    ) -> Result<Self, std::io::Error> {
+        enable_keepalive(socket2::SockRef::from(&socket), keepalive)?;
+        socket.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        Ok(Self { socket, readonly })
+    }
 
-        enable_keepalive(&socket, keepalive)?;
+    /// The address of the remote end of this connection.
+    pub fn peer_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.socket.peer_addr()
+    }
+}
 
+/// A TCP listener, accepting connections from devices which connect out to us.
+#[derive(Debug)]
+pub struct Listener {
+    listener: TcpListener,
+    readonly: bool,
+    keepalive: TcpKeepaliveConfig,
+}
+
+impl Listener {
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        readonly: bool,
+        keepalive: TcpKeepaliveConfig,
+    ) -> Result<Self, std::io::Error> {
         Ok(Self {
-            socket,
+            listener: TcpListener::bind(addr)?,
             readonly,
+            keepalive,
         })
     }
+
+    /// The address on which this listener is listening.
+    pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.listener.local_addr()
+    }
+
+    /// Wait for the next inbound connection.
+    pub fn accept(&self) -> Result<Connection, std::io::Error> {
+        let (socket, _) = self.listener.accept()?;
+        Connection::new(socket, self.readonly, self.keepalive)
+    }
 }
 
-impl super::Connection for Connection {}
+impl super::Connection for Connection {
+    fn set_read_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        // A zero timeout would mean blocking forever
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))
+    }
+}
 
 impl Read for Connection {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
@@ -34,12 +96,18 @@ impl Read for Connection {
     }
 }
 
+#[cfg(feature = "transmit")]
+impl super::ReadWriteConnection for Connection {}
+
+#[cfg(feature = "transmit")]
 impl Write for Connection {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         if self.readonly {
             Err(std::io::ErrorKind::Unsupported.into())
         } else {
-            self.socket.write(buf)
+            let n = self.socket.write(buf)?;
+            super::count_written(n);
+            Ok(n)
         }
     }
 
@@ -53,11 +121,9 @@ impl Write for Connection {
 }
 
 /// Enable TCP keepalive and configure its parameters as supported by the platform.
-fn enable_keepalive(socket: &TcpStream, cfg: TcpKeepaliveConfig) -> std::io::Result<()> {
+fn enable_keepalive(sock: socket2::SockRef<'_>, cfg: TcpKeepaliveConfig) -> std::io::Result<()> {
     //use std::os::unix::prelude::AsRawFd;
-    use socket2::{Socket, TcpKeepalive};
-
-    let sock = Socket::from(socket.try_clone()?);
+    use socket2::TcpKeepalive;
 
     let mut keepalive = TcpKeepalive::new();
 
@@ -88,3 +154,35 @@ fn enable_keepalive(socket: &TcpStream, cfg: TcpKeepaliveConfig) -> std::io::Res
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn listen() {
+        let keepalive = TcpKeepaliveConfig {
+            idle: Duration::from_secs(30),
+            interval: Duration::from_secs(10),
+            count: 5,
+        };
+        let listener = Listener::bind("127.0.0.1:0", true, keepalive).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Accept several connections one after another
+        for message in [b"\x00\xff", b"\x7e\x07"] {
+            let mut client = TcpStream::connect(addr).unwrap();
+            let mut conn = listener.accept().unwrap();
+            client.write_all(message).unwrap();
+
+            let mut buffer = [0u8; 2];
+            conn.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, message);
+
+            // Read-only connections refuse to write
+            #[cfg(feature = "transmit")]
+            assert!(conn.write(b"\x00").is_err());
+        }
+    }
+}
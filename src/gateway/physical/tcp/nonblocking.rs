@@ -0,0 +1,155 @@
+//! TCP connections for use with tokio.
+
+use super::enable_keepalive;
+use crate::config::TcpKeepaliveConfig;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+#[cfg(feature = "transmit")]
+use tokio::io::AsyncWrite;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// A TCP serial connection for use with tokio.
+///
+/// Unlike `Connection`, reads don't time out; callers wanting to notice an idle connection can use
+/// `tokio::time::timeout()`.
+#[derive(Debug)]
+pub struct AsyncConnection {
+    socket: TcpStream,
+    #[cfg_attr(not(feature = "transmit"), allow(dead_code))]
+    readonly: bool,
+}
+
+impl AsyncConnection {
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        readonly: bool,
+        keepalive: TcpKeepaliveConfig,
+    ) -> Result<Self, std::io::Error> {
+        let socket = TcpStream::connect(addr).await?;
+        Self::new(socket, readonly, keepalive)
+    }
+
+    fn new(
+        socket: TcpStream,
+        readonly: bool,
+        keepalive: TcpKeepaliveConfig,
+    ) -> Result<Self, std::io::Error> {
+        enable_keepalive(socket2::SockRef::from(&socket), keepalive)?;
+        Ok(Self { socket, readonly })
+    }
+
+    /// The address of the remote end of this connection.
+    pub fn peer_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.socket.peer_addr()
+    }
+}
+
+/// A TCP listener for use with tokio, accepting connections from devices which connect out to us.
+#[derive(Debug)]
+pub struct AsyncListener {
+    listener: TcpListener,
+    readonly: bool,
+    keepalive: TcpKeepaliveConfig,
+}
+
+impl AsyncListener {
+    pub async fn bind<A: ToSocketAddrs>(
+        addr: A,
+        readonly: bool,
+        keepalive: TcpKeepaliveConfig,
+    ) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            readonly,
+            keepalive,
+        })
+    }
+
+    /// The address on which this listener is listening.
+    pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.listener.local_addr()
+    }
+
+    /// Wait for the next inbound connection.
+    pub async fn accept(&self) -> Result<AsyncConnection, std::io::Error> {
+        let (socket, _) = self.listener.accept().await?;
+        AsyncConnection::new(socket, self.readonly, self.keepalive)
+    }
+}
+
+impl crate::gateway::physical::AsyncConnection for AsyncConnection {}
+
+impl AsyncRead for AsyncConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.socket).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "transmit")]
+impl AsyncWrite for AsyncConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        if self.readonly {
+            return Poll::Ready(Err(std::io::ErrorKind::Unsupported.into()));
+        }
+
+        let result = std::task::ready!(Pin::new(&mut self.socket).poll_write(cx, buf));
+        if let Ok(n) = result {
+            crate::gateway::physical::count_written(n);
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        if self.readonly {
+            Poll::Ready(Ok(()))
+        } else {
+            Pin::new(&mut self.socket).poll_flush(cx)
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.socket).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn listen() {
+        let keepalive = TcpKeepaliveConfig {
+            idle: Duration::from_secs(30),
+            interval: Duration::from_secs(10),
+            count: 5,
+        };
+        let listener = AsyncListener::bind("127.0.0.1:0", true, keepalive)
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut conn = listener.accept().await.unwrap();
+        client.write_all(b"\x00\xff\x7e\x07").await.unwrap();
+
+        let mut buffer = [0u8; 4];
+        conn.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"\x00\xff\x7e\x07");
+
+        // Read-only connections refuse to write
+        #[cfg(feature = "transmit")]
+        assert!(conn.write(b"\x00").await.is_err());
+    }
+}
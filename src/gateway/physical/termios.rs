@@ -1,19 +1,30 @@
+use crate::config::{Parity, SerialLineConfig, StopBits};
 use libc::{
-    cfsetspeed, tcgetattr, tcsetattr, termios, B38400, CLOCAL, CREAD, CRTSCTS, CS8, CSIZE, CSTOPB,
-    ECHO, ISIG, IXANY, IXOFF, IXON, OCRNL, ONLCR, OPOST, PARENB, TCSANOW, VMIN, VTIME,
+    cfsetspeed, speed_t, tcgetattr, tcsetattr, termios, BRKINT, CLOCAL, CREAD, CRTSCTS, CS8, CSIZE,
+    CSTOPB, ECHO, ICANON, ICRNL, IEXTEN, IGNBRK, IGNCR, INLCR, INPCK, ISIG, ISTRIP, IXANY, IXOFF,
+    IXON, OCRNL, ONLCR, OPOST, PARENB, PARMRK, PARODD, TCSANOW, VMIN, VTIME,
 };
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 use std::os::unix::io::AsRawFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "async")]
+pub use nonblocking::AsyncPort;
 
 /// An open serial port.
 #[derive(Debug)]
 pub struct Port {
     file: std::fs::File,
+    read_timeout: Option<Duration>,
 }
 
 impl Port {
-    pub fn open<P: AsRef<Path>>(device: P) -> Result<Self, Error> {
+    pub fn open<P: AsRef<Path>>(device: P, line: &SerialLineConfig) -> Result<Self, Error> {
+        let speed = speed(line.baud_rate)?;
+
         // Open the path which hopefully points to a serial port
         let file = std::fs::File::options()
             .read(true)
@@ -29,15 +40,22 @@ impl Port {
                 return Err(Error::last_os_error());
             }
 
-            // Use the helper ot set 38400 baud
-            if cfsetspeed(&mut tty as *mut _, B38400) != 0 {
+            // Use the helper to set the baud rate
+            if cfsetspeed(&mut tty as *mut _, speed) != 0 {
                 return Err(Error::last_os_error());
             }
 
             // Now, in the structure directly, set:
             tty.c_cflag = (tty.c_cflag & !CSIZE) | CS8; // 8
-            tty.c_cflag &= !PARENB; // N
-            tty.c_cflag &= !CSTOPB; // 1
+            match line.parity {
+                Parity::None => tty.c_cflag &= !PARENB,
+                Parity::Odd => tty.c_cflag |= PARENB | PARODD,
+                Parity::Even => tty.c_cflag = (tty.c_cflag | PARENB) & !PARODD,
+            }
+            match line.stop_bits {
+                StopBits::One => tty.c_cflag &= !CSTOPB,
+                StopBits::Two => tty.c_cflag |= CSTOPB,
+            }
 
             tty.c_cflag &= !CRTSCTS; // no hardware flow control
             tty.c_iflag &= !(IXON | IXOFF | IXANY); // no software flow control
@@ -45,11 +63,14 @@ impl Port {
             tty.c_cflag |= CREAD; // enable receiving
             tty.c_lflag &= !ECHO; // no local echo
             tty.c_lflag &= !ISIG; // don't interpret signal characters
+            tty.c_lflag &= !(ICANON | IEXTEN); // don't wait for lines or edit them
+            tty.c_iflag &= !(IGNBRK | BRKINT | PARMRK | ISTRIP | INPCK); // pass every byte through as is
+            tty.c_iflag &= !(INLCR | IGNCR | ICRNL); // specifically don't mangle CR/LF
             tty.c_oflag &= !OPOST; // don't post-process the output
             tty.c_oflag &= !(ONLCR | OCRNL); // specifically don't mangle CR/LF
 
-            tty.c_cc[VMIN] = 1; // read at least 1 byte
-            tty.c_cc[VTIME] = 0; // wait any amount of time for that byte
+            tty.c_cc[VMIN] = 1; // read at least 1 byte, waiting any amount of time for it
+            tty.c_cc[VTIME] = vtime(line.read_timeout); // then wait this long for more
 
             // Update the FD
             if tcsetattr(fd, TCSANOW, &tty as *const _) != 0 {
@@ -57,22 +78,197 @@ impl Port {
             }
         }
 
-        Ok(Self { file })
+        super::serial_line::configure(file.as_raw_fd(), line)?;
+
+        Ok(Self {
+            file,
+            read_timeout: None,
+        })
+    }
+
+    /// Wait until the port is readable, or `timeout` passes.
+    fn poll(&self, timeout: Duration) -> std::io::Result<()> {
+        let mut fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            0 => Err(ErrorKind::TimedOut.into()),
+            n if n < 0 => Err(Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Convert a baud rate into the corresponding `termios` speed.
+fn speed(baud_rate: u32) -> Result<speed_t, Error> {
+    use libc::*;
+    Ok(match baud_rate {
+        1200 => B1200,
+        2400 => B2400,
+        4800 => B4800,
+        9600 => B9600,
+        19200 => B19200,
+        38400 => B38400,
+        57600 => B57600,
+        115200 => B115200,
+        230400 => B230400,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unsupported baud rate {}", baud_rate),
+            ))
+        }
+    })
+}
+
+/// Convert a read timeout in milliseconds into `VTIME`, which counts tenths of a second.
+///
+/// Timeouts below 100 ms round down to returning whatever has been received straight away.
+fn vtime(read_timeout: u64) -> libc::cc_t {
+    (read_timeout / 100).min(libc::cc_t::MAX as u64) as libc::cc_t
+}
+
+/// List the serial ports on this system by scanning `/dev` for `tty*` devices.
+///
+/// This is a fallback for systems without `libudev`. Virtual consoles (`/dev/tty`, `/dev/tty0`…)
+/// are skipped, but no attempt is made to check whether hardware is attached to a port.
+pub fn available_ports() -> Result<Vec<PathBuf>, Error> {
+    let mut ports = std::fs::read_dir("/dev")?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            let suffix = name.strip_prefix("tty")?;
+            suffix
+                .starts_with(|c: char| c.is_ascii_alphabetic())
+                .then_some(path)
+        })
+        .collect::<Vec<_>>();
+    ports.sort();
+    Ok(ports)
+}
+
+impl super::Connection for Port {
+    fn set_read_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.read_timeout = Some(timeout);
+        Ok(())
     }
 }
 
 impl std::io::Read for Port {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(timeout) = self.read_timeout {
+            self.poll(timeout)?;
+        }
         self.file.read(buf)
     }
 }
 
+#[cfg(feature = "transmit")]
+impl super::ReadWriteConnection for Port {}
+
+#[cfg(feature = "transmit")]
 impl std::io::Write for Port {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.file.write(buf)
+        let n = self.file.write(buf)?;
+        super::count_written(n);
+        Ok(n)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         self.file.flush()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::io::{Read, Write};
+    use std::os::fd::FromRawFd;
+
+    /// Open a pseudoterminal to stand in for a serial port, returning the master and its name.
+    pub(super) fn pty() -> (std::fs::File, String) {
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(master >= 0);
+            assert_eq!(libc::grantpt(master), 0);
+            assert_eq!(libc::unlockpt(master), 0);
+            let name = CStr::from_ptr(libc::ptsname(master))
+                .to_str()
+                .unwrap()
+                .to_owned();
+            (std::fs::File::from_raw_fd(master), name)
+        }
+    }
+
+    #[test]
+    fn raw_bytes() {
+        let (mut master, name) = pty();
+        let mut port = Port::open(name, &SerialLineConfig::default()).unwrap();
+
+        // Bytes arrive as they are, without waiting for a newline or translating CR/LF
+        master
+            .write_all(b"\x7e\x07\x0d\x00\x0a\x7e\x08\x0d")
+            .unwrap();
+        let mut buffer = [0u8; 8];
+        port.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"\x7e\x07\x0d\x00\x0a\x7e\x08\x0d");
+
+        #[cfg(feature = "transmit")]
+        {
+            port.write_all(b"\x0a\x0d").unwrap();
+            let mut buffer = [0u8; 2];
+            master.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, b"\x0a\x0d");
+        }
+    }
+
+    #[test]
+    fn read_timeout() {
+        use crate::gateway::physical::Connection;
+
+        let (mut master, name) = pty();
+        let mut port = Port::open(name, &SerialLineConfig::default()).unwrap();
+        port.set_read_timeout(Duration::from_millis(20)).unwrap();
+
+        let mut buffer = [0u8; 2];
+        let error = port.read(&mut buffer).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+
+        master.write_all(b"\x7e\x08").unwrap();
+        port.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"\x7e\x08");
+    }
+
+    #[test]
+    fn line_settings() {
+        let (_master, name) = pty();
+        let line = SerialLineConfig {
+            baud_rate: 9600,
+            parity: Parity::Even,
+            stop_bits: StopBits::Two,
+            read_timeout: 250,
+            ..SerialLineConfig::default()
+        };
+        let port = Port::open(&name, &line).unwrap();
+
+        let tty = unsafe {
+            let mut tty: termios = std::mem::zeroed();
+            assert_eq!(tcgetattr(port.file.as_raw_fd(), &mut tty), 0);
+            tty
+        };
+        assert_eq!(unsafe { libc::cfgetospeed(&tty) }, libc::B9600);
+        // Pseudoterminals ignore parity, but they do keep the stop bits
+        assert_eq!(tty.c_cflag & CSTOPB, CSTOPB);
+        assert_eq!(tty.c_cc[VTIME], 2);
+
+        let line = SerialLineConfig {
+            baud_rate: 1234,
+            ..SerialLineConfig::default()
+        };
+        assert!(Port::open(&name, &line).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Serial ports for use with tokio.
+
+use super::Port;
+use crate::config::SerialLineConfig;
+use std::fs::File;
+use std::io::{Error, Read};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// An open serial port for use with tokio.
+///
+/// The port is configured exactly like a `Port`, then switched to non-blocking mode and registered
+/// with the tokio reactor.
+#[derive(Debug)]
+pub struct AsyncPort {
+    file: AsyncFd<File>,
+}
+
+impl AsyncPort {
+    /// Open and configure a serial port. This must be called from within a tokio runtime.
+    pub fn open<P: AsRef<Path>>(device: P, line: &SerialLineConfig) -> Result<Self, Error> {
+        let Port { file, .. } = Port::open(device, line)?;
+
+        let fd = file.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+
+        Ok(Self {
+            file: AsyncFd::new(file)?,
+        })
+    }
+}
+
+impl crate::gateway::physical::AsyncConnection for AsyncPort {}
+
+impl AsyncRead for AsyncPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.file.poll_read_ready(cx))?;
+            match guard.try_io(|file| file.get_ref().read(buf.initialize_unfilled())) {
+                Ok(result) => {
+                    let n = result?;
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "transmit")]
+impl tokio::io::AsyncWrite for AsyncPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        use std::io::Write;
+
+        loop {
+            let mut guard = ready!(self.file.poll_write_ready(cx))?;
+            match guard.try_io(|file| file.get_ref().write(buf)) {
+                Ok(result) => {
+                    if let Ok(n) = result {
+                        crate::gateway::physical::count_written(n);
+                    }
+                    return Poll::Ready(result);
+                }
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::pty;
+    use super::*;
+    use std::io::Write;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn raw_bytes() {
+        let (mut master, name) = pty();
+        let mut port = AsyncPort::open(name, &SerialLineConfig::default()).unwrap();
+
+        master
+            .write_all(b"\x7e\x07\x0d\x00\x0a\x7e\x08\x0d")
+            .unwrap();
+        let mut buffer = [0u8; 8];
+        port.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"\x7e\x07\x0d\x00\x0a\x7e\x08\x0d");
+
+        #[cfg(feature = "transmit")]
+        {
+            use tokio::io::AsyncWriteExt;
+
+            port.write_all(b"\x0a\x0d").await.unwrap();
+            let mut buffer = [0u8; 2];
+            master.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, b"\x0a\x0d");
+        }
+    }
+}
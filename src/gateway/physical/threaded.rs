@@ -0,0 +1,150 @@
+//! Blocking connections, read on a thread of their own for use with tokio.
+//!
+//! Some sources have no non-blocking equivalent: standard input, files being followed, and traced
+//! processes, which can only be traced from the thread which attached to them. A [`Threaded`]
+//! connection opens and reads such a source on a dedicated thread, and hands over what it reads.
+
+use super::{AsyncConnection, Connection};
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::{mpsc, oneshot};
+
+/// How many reads may be waiting to be polled before the thread stops reading.
+const QUEUE_LENGTH: usize = 64;
+
+/// A connection which is read by a dedicated thread.
+///
+/// The thread exits at the end of the connection, after an error, or once the `Threaded` has been
+/// dropped and the read in progress returns.
+#[derive(Debug)]
+pub struct Threaded {
+    receiver: mpsc::Receiver<Result<Vec<u8>, Error>>,
+    pending: Vec<u8>,
+}
+
+impl Threaded {
+    /// Call `open` on a new thread named `name`, and keep reading the connection it returns there.
+    pub async fn open<F, C>(name: &str, open: F) -> Result<Self, Error>
+    where
+        F: FnOnce() -> Result<C, Error> + Send + 'static,
+        C: Connection + 'static,
+    {
+        let (opened, opened_receiver) = oneshot::channel();
+        let (sender, receiver) = mpsc::channel(QUEUE_LENGTH);
+
+        std::thread::Builder::new()
+            .name(name.into())
+            .spawn(move || match open() {
+                Ok(conn) => {
+                    opened.send(Ok(())).ok();
+                    read(conn, sender);
+                }
+                Err(e) => {
+                    opened.send(Err(e)).ok();
+                }
+            })?;
+
+        opened_receiver
+            .await
+            .map_err(|_| Error::other("connection thread exited"))??;
+
+        Ok(Self {
+            receiver,
+            pending: Vec::new(),
+        })
+    }
+}
+
+/// Read `conn` until it ends, fails, or nobody is listening any more.
+fn read<C: Connection>(mut conn: C, sender: mpsc::Sender<Result<Vec<u8>, Error>>) {
+    let mut buffer = [0u8; 1024];
+    loop {
+        let result = match conn.read(&mut buffer) {
+            // Dropping the sender is the end of the input
+            Ok(0) => return,
+            Ok(n) => Ok(buffer[..n].to_vec()),
+            // Idle connections are detected on the async side
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                if sender.is_closed() {
+                    return;
+                }
+                continue;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+
+        let failed = result.is_err();
+        if sender.blocking_send(result).is_err() || failed {
+            return;
+        }
+    }
+}
+
+impl AsyncConnection for Threaded {}
+
+impl AsyncRead for Threaded {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.pending.is_empty() {
+            match ready!(this.receiver.poll_recv(cx)) {
+                Some(Ok(bytes)) => this.pending = bytes,
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let n = this.pending.len().min(buf.remaining());
+        buf.put_slice(&this.pending[..n]);
+        this.pending.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use tokio::io::AsyncReadExt;
+
+    #[derive(Debug)]
+    struct Bytes(Cursor<Vec<u8>>);
+
+    impl Connection for Bytes {}
+
+    impl Read for Bytes {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            // Hand out a few bytes at a time
+            let n = buf.len().min(3);
+            Read::read(&mut self.0, &mut buf[..n])
+        }
+    }
+
+    #[tokio::test]
+    async fn read_to_end() {
+        let data = crate::test_data::ENUMERATION_SEQUENCE.to_vec();
+        let expected = data.clone();
+        let mut conn = Threaded::open("test", move || Ok(Bytes(Cursor::new(data))))
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        conn.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn open_error() {
+        let result = Threaded::open("test", || -> Result<Bytes, Error> {
+            Err(ErrorKind::NotFound.into())
+        })
+        .await;
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
@@ -7,26 +7,44 @@
 //! This module inspects the `/proc` filesystem to identify `meshdcd` and the file descriptor of the
 //! local serial port. It then uses `ptrace()` to attach and intercept system calls. When `meshdcd`
 //! `read()` or `write()`s the serial port, this module reads the buffer containing the serial data.
-
-use std::error::Error;
+//!
+//! Both directions are bus traffic: `meshdcd` writes the controller's commands and reads the
+//! gateway's responses. Feeding both to a `gateway::link::Receiver` decodes the whole conversation.
 
 mod target;
 mod traced_process;
 
-use target::Target;
-use traced_process::TracedProcess;
+pub use target::{FindTargetError, Target};
+pub use traced_process::{AttachError, TraceError, TracedProcess};
+
+/// The name of the process which Tigo controllers use to talk to their gateways.
+pub const DEFAULT_PROCESS_NAME: &str = "meshdcd";
+
+/// Which way data passed through the traced serial port.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    /// Data the traced process `read()` from the serial port, i.e. received from the bus.
+    Read,
+    /// Data the traced process `write()` to the serial port, i.e. transmitted onto the bus.
+    Write,
+}
 
 #[derive(thiserror::Error, Debug)]
-enum OpenError {
+pub enum OpenError {
     #[error("error finding target process: {0}")]
-    FindingTarget(#[from] target::FindTargetError),
-    #[error("error attaching to `meshdcd` target: {0}")]
-    Attaching(#[from] traced_process::AttachError),
+    FindingTarget(#[from] FindTargetError),
+    #[error("error attaching to target: {0}")]
+    Attaching(#[from] AttachError),
 }
 
-pub fn open() -> Result<impl Iterator<Item = Blob>, impl Error> {
-    let target = Target::find().map_err(OpenError::from)?;
-    let attached =
-        TracedProcess::new(target.meshdcd_pid, target.meshdcd_tty_fd).map_err(OpenError::from)?;
-    Ok(attached)
+/// Find the process named `process_name` and start tracing its serial port.
+pub fn open(process_name: &str) -> Result<TracedProcess, OpenError> {
+    let target = Target::find(process_name)?;
+    log::info!(
+        "tracing {} (pid {}, fd {})",
+        process_name,
+        target.pid,
+        target.tty_fd
+    );
+    Ok(TracedProcess::new(target.pid, target.tty_fd)?)
 }
@@ -2,10 +2,11 @@ use libc::{c_int, pid_t};
 use std::fs;
 use std::path::PathBuf;
 
+/// A process to trace, and the file descriptor of its serial port.
 #[derive(Debug, Clone)]
 pub struct Target {
-    pub(crate) meshdcd_pid: pid_t,
-    pub(crate) meshdcd_tty_fd: c_int,
+    pub pid: pid_t,
+    pub tty_fd: c_int,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -16,12 +17,12 @@ pub enum FindTargetError {
     ReadPidExe(pid_t, std::io::Error),
     #[error("reading /proc/{0}/fd: {1}")]
     ReadPidFds(pid_t, std::io::Error),
-    #[error("no `meshdcd` process found")]
-    NoMeshdcdProcess,
-    #[error("`meshdcd` has no open tty")]
-    MeshdcdHasNoTty,
-    #[error("`meshdcd` has multiple open ttys")]
-    MeshdcdHasMultipleTtys,
+    #[error("no `{0}` process found")]
+    NoProcess(String),
+    #[error("`{0}` has no open tty")]
+    NoTty(String),
+    #[error("`{0}` has multiple open ttys")]
+    MultipleTtys(String),
 }
 
 type Result<T, E = FindTargetError> = std::result::Result<T, E>;
@@ -41,14 +42,15 @@ fn read_proc_pids() -> Result<impl Iterator<Item = Result<pid_t>>> {
     }))
 }
 
-fn is_meshdcd(pid: pid_t) -> bool {
+fn is_process(pid: pid_t, name: &str) -> bool {
     // Failures here might mean that the process exited while we're identifying it
     // Ignore them
     fs::read_link(format!("/proc/{}/exe", pid))
         .ok()
         .map(|path| {
-            // Is this meshdcd?
-            path.as_os_str().as_encoded_bytes().ends_with(b"/meshdcd")
+            // Is this the process we're looking for?
+            path.file_name()
+                .is_some_and(|file_name| file_name.as_encoded_bytes() == name.as_bytes())
         })
         .unwrap_or(false)
 }
@@ -74,35 +76,35 @@ fn fds(pid: pid_t) -> Result<Vec<(c_int, PathBuf)>> {
 }
 
 impl Target {
-    pub fn find() -> Result<Self, FindTargetError> {
-        // Find meshdcd
-        let meshdcd_pid = read_proc_pids()?
+    /// Find the process whose executable is named `process_name`, e.g. `meshdcd`.
+    pub fn find(process_name: &str) -> Result<Self, FindTargetError> {
+        // Find the process
+        let pid = read_proc_pids()?
             .filter_map(|pid| match pid {
-                Ok(pid) if is_meshdcd(pid) => Some(Ok(pid)),
+                Ok(pid) if is_process(pid, process_name) => Some(Ok(pid)),
                 Ok(_) => None,
                 Err(e) => Some(Err(e)),
             })
             .next()
-            .ok_or(FindTargetError::NoMeshdcdProcess)??;
+            .ok_or_else(|| FindTargetError::NoProcess(process_name.into()))??;
 
-        // Now that we have meshdcd, find which file descriptor points to a TTY
-        let mut tty_fds = fds(meshdcd_pid)?
+        // Now that we have the process, find which file descriptor points to a TTY
+        let mut tty_fds = fds(pid)?
             .into_iter()
-            .filter(|(fd, target)| {
+            .filter(|(_, target)| {
                 target
                     .as_os_str()
                     .as_encoded_bytes()
                     .starts_with(b"/dev/tty")
             })
             .map(|(fd, _)| fd);
-        let meshdcd_tty_fd = tty_fds.next().ok_or(FindTargetError::MeshdcdHasNoTty)?;
+        let tty_fd = tty_fds
+            .next()
+            .ok_or_else(|| FindTargetError::NoTty(process_name.into()))?;
         if tty_fds.next().is_some() {
-            return Err(FindTargetError::MeshdcdHasMultipleTtys);
+            return Err(FindTargetError::MultipleTtys(process_name.into()));
         }
 
-        Ok(Target {
-            meshdcd_pid,
-            meshdcd_tty_fd,
-        })
+        Ok(Target { pid, tty_fd })
     }
 }
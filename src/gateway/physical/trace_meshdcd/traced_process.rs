@@ -1,19 +1,32 @@
-use crate::tap::{Event, Timestamp};
-use libc::{c_char, c_int, pid_t, waitpid, SIGTRAP, WIFSTOPPED, WSTOPSIG};
+use super::Direction;
+use libc::{c_int, c_long, c_void, pid_t, waitpid, SIGSTOP, SIGTRAP, WIFSTOPPED, WSTOPSIG};
 use libc::{
-    siginfo_t, user_regs, ESRCH, PTRACE_ATTACH, PTRACE_DETACH, PTRACE_GETREGS,
-    PTRACE_O_TRACESYSGOOD, PTRACE_SETOPTIONS, PTRACE_SYSCALL, __WALL,
+    __WALL, ESRCH, PTRACE_ATTACH, PTRACE_DETACH, PTRACE_GETREGSET, PTRACE_O_TRACESYSGOOD,
+    PTRACE_SETOPTIONS, PTRACE_SYSCALL, WIFEXITED, WIFSIGNALED,
 };
+use std::io::Read;
 use std::os::unix::fs::FileExt;
 use std::ptr::null_mut;
 
 pub type Result<T, E = TraceError> = std::result::Result<T, E>;
 
+/// A process attached with `ptrace()`, whose reads and writes of one file descriptor are observed.
+///
+/// As an `Iterator`, a `TracedProcess` yields the data passing through the file descriptor along
+/// with its `Direction`, ending when the process terminates. As a `Read`er, it yields the same
+/// data with both directions interleaved, which is what a `gateway::link::Receiver` wants.
+///
+/// The process is stopped while nobody is asking for its next read or write.
 #[derive(Debug)]
 pub struct TracedProcess {
     pid: pid_t,
     fd: c_int,
     mem: std::fs::File,
+    /// A signal to deliver when the process is next resumed
+    pending_signal: c_int,
+    /// Data which `Read` has not yet returned
+    unread: Vec<u8>,
+    unread_offset: usize,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -27,25 +40,31 @@ pub enum AttachError {
 }
 
 impl TracedProcess {
-    pub(crate) fn new(pid: pid_t, fd: c_int) -> Result<Self, AttachError> {
+    /// Attach to the process `pid`, observing its reads and writes of `fd`.
+    pub fn new(pid: pid_t, fd: c_int) -> Result<Self, AttachError> {
         assert_ne!(pid, 0);
 
         // Attach the process
-        unsafe { ptrace(PTRACE_ATTACH, pid, null_mut(), 0) }.map_err(AttachError::Ptrace)?;
+        unsafe { ptrace(PTRACE_ATTACH, pid, null_mut(), null_mut()) }
+            .map_err(AttachError::Ptrace)?;
 
         // Open memory
-        let mem = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(format!("/proc/{}/mem", pid));
+        let mem = std::fs::File::open(format!("/proc/{}/mem", pid));
         let mem = mem.map_err(|e| {
             // We're about to fail, but make sure we don't leave them hanging
-            must_detach(pid);
+            detach(pid);
             AttachError::OpeningMem(e)
         })?;
 
         // We can construct a TracedProcess, and we should since we want RAII
-        let mut this = Self { pid, fd, mem };
+        let mut this = Self {
+            pid,
+            fd,
+            mem,
+            pending_signal: 0,
+            unread: Vec::new(),
+            unread_offset: 0,
+        };
 
         // Finish setup
         this.setup().map_err(AttachError::TraceSetup)?;
@@ -54,21 +73,34 @@ impl TracedProcess {
     }
 
     fn setup(&mut self) -> Result<()> {
+        // PTRACE_ATTACH sent a SIGSTOP, which we must wait for before doing anything else
+        loop {
+            let status = self.wait_for_stop()?;
+            if WSTOPSIG(status) == SIGSTOP {
+                break;
+            }
+
+            // It stopped for some other reason first
+            self.pending_signal = WSTOPSIG(status);
+            self.continue_until_syscall()?;
+        }
+
         // Indicate that we want to identify system calls more easily
         unsafe {
             ptrace(
                 PTRACE_SETOPTIONS,
                 self.pid,
                 null_mut(),
-                PTRACE_O_TRACESYSGOOD,
+                PTRACE_O_TRACESYSGOOD as _,
             )?;
         }
 
         Ok(())
     }
 
+    /// Stop tracing, letting the process continue on its own.
     pub fn detach(mut self) {
-        must_detach(self.pid);
+        detach(self.pid);
         self.pid = 0;
     }
 
@@ -79,6 +111,10 @@ impl TracedProcess {
             let pid = unsafe { waitpid(self.pid, &mut status as *mut c_int, __WALL) };
             if pid > 0 && WIFSTOPPED(status) {
                 return Ok(status);
+            } else if pid > 0 && (WIFEXITED(status) || WIFSIGNALED(status)) {
+                // There's nothing left to detach
+                self.pid = 0;
+                return Err(TraceError::ProcessTerminated);
             } else if pid < 0 {
                 return Err(TraceError::errno());
             }
@@ -96,117 +132,102 @@ impl TracedProcess {
             }
 
             // It stopped for some other reason
-            // Resume
+            // Resume, delivering the signal it would have received if we weren't here
+            if WSTOPSIG(status) != SIGSTOP && WSTOPSIG(status) != SIGTRAP {
+                self.pending_signal = WSTOPSIG(status);
+            }
             self.continue_until_syscall()?;
         }
     }
 
-    fn continue_until_syscall(&mut self) -> Result<(), TraceError> {
-        unsafe { ptrace(PTRACE_SYSCALL, self.pid, null_mut(), 0) }
-            .map_err(|e| {
-                eprintln!("PTRACE_SYSCALL error");
-                e
-            })
+    fn continue_until_syscall(&mut self) -> Result<()> {
+        let signal = std::mem::take(&mut self.pending_signal);
+        unsafe { ptrace(PTRACE_SYSCALL, self.pid, null_mut(), signal as _) }
+            .inspect_err(|_| log::debug!("PTRACE_SYSCALL error"))
             .map(|_| ())
     }
 
-    fn wait_for_next_event(&mut self) -> Result<Event, TraceError> {
+    fn wait_for_next_event(&mut self) -> Result<(Direction, Vec<u8>)> {
         loop {
+            // The process is stopped until we ask for the next event
+            self.continue_until_syscall()?;
             self.wait_for_syscall_stop()?;
 
-            // We're stopped at a syscall
+            // We're stopped at a syscall entry
             // Get registers
-            let regs = get_registers(self.pid)?;
+            let call = get_registers(self.pid)?;
 
             // Wait for the syscall to return
             self.continue_until_syscall()?;
-            self.wait_for_stop()?;
+            self.wait_for_syscall_stop()?;
 
             // Do we care about this syscall?
-            // syscall # is r7, args are r0…r6
-            let event = match regs.arm_r7 {
-                SYSCALL_READ if regs.arm_r0 == self.fd as _ => {
-                    // We're reading the FD to trace
-                    // Wait for the system call to return
-                    self.generate_read_event(regs)?
-                }
-                SYSCALL_WRITE if regs.arm_r0 == self.fd as _ => {
-                    // We're writing the FD to trace
-                    // Wait for the system call to return
-                    self.generate_write_event(regs)?
-                }
-                _ => {
-                    // Nah
-                    None
-                }
+            let (number, fd, buffer_ptr) = regs::syscall(&call);
+            let direction = match number {
+                SYSCALL_READ if fd == self.fd as c_long => Direction::Read,
+                SYSCALL_WRITE if fd == self.fd as c_long => Direction::Write,
+                _ => continue,
             };
-            self.continue_until_syscall()?;
 
-            if let Some(e) = event {
-                return Ok(e);
+            // We're reading or writing the FD to trace
+            let bytes = regs::return_value(&get_registers(self.pid)?);
+            if bytes <= 0 {
+                // made no progress
+                continue;
             }
 
-            // Go around again
+            let mut buffer = vec![0u8; bytes as usize];
+            self.mem
+                .read_exact_at(&mut buffer, buffer_ptr)
+                .map_err(TraceError::MemoryReadError)?;
+            return Ok((direction, buffer));
         }
     }
+}
 
-    fn generate_read_event(&self, call_regs: user_regs) -> Result<Option<Event>> {
-        let now = Timestamp::now();
-
-        let return_regs = get_registers(self.pid)?;
-        let buffer_ptr = call_regs.arm_r1;
+impl Iterator for TracedProcess {
+    type Item = Result<(Direction, Vec<u8>)>;
 
-        let bytes_read = return_regs.arm_r0 as isize;
-        if bytes_read < 0 {
-            // made no progress
-            return Ok(None);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pid == 0 {
+            return None;
         }
 
-        let mut buffer = vec![0u8; bytes_read as usize];
-        self.mem
-            .read_at(&mut buffer, buffer_ptr as _)
-            .map_err(TraceError::MemoryReadError)?;
-        Ok(Some(Event::SerialRx(now, buffer)))
-    }
-
-    fn generate_write_event(&self, call_regs: user_regs) -> Result<Option<Event>> {
-        let now = Timestamp::now();
-
-        let return_regs = get_registers(self.pid)?;
-        let buffer_ptr = call_regs.arm_r1;
-
-        let bytes = return_regs.arm_r0 as isize;
-        if bytes < 0 {
-            // made no progress
-            return Ok(None);
+        match self.wait_for_next_event() {
+            Ok(event) => Some(Ok(event)),
+            Err(TraceError::ProcessTerminated) => None,
+            Err(e) => Some(Err(e)),
         }
-
-        let mut buffer = vec![0u8; bytes as usize];
-        self.mem
-            .read_at(&mut buffer, buffer_ptr as _)
-            .map_err(TraceError::MemoryReadError)?;
-        Ok(Some(Event::SerialTx(now, buffer)))
     }
 }
 
-impl Iterator for TracedProcess {
-    type Item = super::Event;
+const SYSCALL_READ: c_long = libc::SYS_read;
+const SYSCALL_WRITE: c_long = libc::SYS_write;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.wait_for_next_event() {
-            Ok(e) => Some(e),
-            Err(e) => return Some(Event::Error(e)),
+impl super::super::Connection for TracedProcess {}
+
+impl Read for TracedProcess {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.unread_offset == self.unread.len() {
+            // Get more data, returning end of input when the process terminates
+            match self.next() {
+                Some(Ok((_, data))) => self.unread = data,
+                Some(Err(e)) => return Err(std::io::Error::other(e)),
+                None => return Ok(0),
+            }
+            self.unread_offset = 0;
         }
+
+        let n = (&self.unread[self.unread_offset..]).read(buf)?;
+        self.unread_offset += n;
+        Ok(n)
     }
 }
 
-const SYSCALL_READ: u32 = 3;
-const SYSCALL_WRITE: u32 = 4;
-
 impl Drop for TracedProcess {
     fn drop(&mut self) {
         if self.pid != 0 {
-            must_detach(self.pid);
+            detach(self.pid);
         }
     }
 }
@@ -234,9 +255,9 @@ impl TraceError {
 unsafe fn ptrace(
     request: libc::c_uint,
     pid: pid_t,
-    addr: *mut libc::c_char,
-    data: libc::c_int,
-) -> Result<c_int, TraceError> {
+    addr: *mut c_void,
+    data: *mut c_void,
+) -> Result<c_long, TraceError> {
     let rv = libc::ptrace(request as _, pid, addr, data);
     if rv == -1 {
         Err(TraceError::errno())
@@ -245,15 +266,166 @@ unsafe fn ptrace(
     }
 }
 
-fn must_detach(pid: pid_t) {
-    unsafe { ptrace(PTRACE_DETACH, pid, null_mut(), 0) }.expect("PTRACE_DETACH");
+fn detach(pid: pid_t) {
+    if let Err(e) = unsafe { ptrace(PTRACE_DETACH, pid, null_mut(), null_mut()) } {
+        log::warn!("PTRACE_DETACH of pid {} failed: {}", pid, e);
+    }
 }
 
-fn get_registers(pid: pid_t) -> Result<user_regs> {
-    let mut regs: user_regs = unsafe { std::mem::zeroed() };
-    unsafe { ptrace(PTRACE_GETREGS, pid, null_mut(), &mut regs as *mut _ as _) }.map_err(|e| {
-        eprintln!("PTRACE_GETREGS error");
-        e
-    })?;
+fn get_registers(pid: pid_t) -> Result<regs::Registers> {
+    let mut regs: regs::Registers = unsafe { std::mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: &mut regs as *mut _ as *mut c_void,
+        iov_len: std::mem::size_of::<regs::Registers>(),
+    };
+    unsafe {
+        ptrace(
+            PTRACE_GETREGSET,
+            pid,
+            libc::NT_PRSTATUS as *mut c_void,
+            &mut iov as *mut _ as *mut c_void,
+        )
+    }
+    .inspect_err(|_| log::debug!("PTRACE_GETREGSET error"))?;
     Ok(regs)
 }
+
+/// Where each architecture keeps system call numbers, arguments and return values.
+mod regs {
+    use libc::c_long;
+
+    #[cfg(target_arch = "arm")]
+    pub type Registers = libc::user_regs;
+
+    /// Returns the system call number, the file descriptor and buffer arguments at syscall entry.
+    #[cfg(target_arch = "arm")]
+    pub fn syscall(regs: &Registers) -> (c_long, c_long, u64) {
+        // syscall # is r7, args are r0…r6
+        (regs.arm_r7 as _, regs.arm_r0 as _, regs.arm_r1 as _)
+    }
+
+    #[cfg(target_arch = "arm")]
+    pub fn return_value(regs: &Registers) -> isize {
+        regs.arm_r0 as i32 as _
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub type Registers = libc::user_regs_struct;
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn syscall(regs: &Registers) -> (c_long, c_long, u64) {
+        // syscall # is x8, args are x0…x5
+        (regs.regs[8] as _, regs.regs[0] as _, regs.regs[1])
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn return_value(regs: &Registers) -> isize {
+        regs.regs[0] as _
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub type Registers = libc::user_regs_struct;
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn syscall(regs: &Registers) -> (c_long, c_long, u64) {
+        // syscall # is orig_rax, args are rdi, rsi, rdx…
+        (regs.orig_rax as _, regs.rdi as _, regs.rsi)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn return_value(regs: &Registers) -> isize {
+        regs.rax as _
+    }
+
+    #[cfg(not(any(target_arch = "arm", target_arch = "aarch64", target_arch = "x86_64")))]
+    compile_error!("tracing `meshdcd` is only supported on arm, aarch64 and x86_64");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::io::Write;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    /// Open a raw pseudoterminal, returning the master and the slave.
+    fn pty() -> (std::fs::File, std::fs::File) {
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(master >= 0);
+            assert_eq!(libc::grantpt(master), 0);
+            assert_eq!(libc::unlockpt(master), 0);
+            let master = std::fs::File::from_raw_fd(master);
+
+            let mut name = [0 as libc::c_char; 64];
+            assert_eq!(
+                libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()),
+                0
+            );
+            let name = CStr::from_ptr(name.as_ptr()).to_str().unwrap();
+            let slave = std::fs::File::options()
+                .read(true)
+                .write(true)
+                .open(name)
+                .unwrap();
+
+            let mut tty: libc::termios = std::mem::zeroed();
+            assert_eq!(libc::tcgetattr(slave.as_raw_fd(), &mut tty), 0);
+            libc::cfmakeraw(&mut tty);
+            assert_eq!(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &tty), 0);
+
+            (master, slave)
+        }
+    }
+
+    #[test]
+    fn pty_echo() {
+        let (mut master, slave) = pty();
+        let fd = slave.as_raw_fd();
+
+        // A dummy `meshdcd` which echoes whatever it reads from its tty
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+        if pid == 0 {
+            unsafe {
+                libc::close(master.as_raw_fd());
+                let mut buffer = [0u8; 64];
+                loop {
+                    let n = libc::read(fd, buffer.as_mut_ptr() as _, buffer.len());
+                    if n <= 0 {
+                        libc::_exit(0);
+                    }
+                    libc::write(fd, buffer.as_ptr() as _, n as _);
+                }
+            }
+        }
+        drop(slave);
+
+        let mut traced = TracedProcess::new(pid, fd).unwrap();
+        master.write_all(b"\x00\xff\x7e\x07").unwrap();
+        assert_eq!(
+            traced.next().unwrap().unwrap(),
+            (Direction::Read, b"\x00\xff\x7e\x07".to_vec())
+        );
+        assert_eq!(
+            traced.next().unwrap().unwrap(),
+            (Direction::Write, b"\x00\xff\x7e\x07".to_vec())
+        );
+
+        let mut echo = [0u8; 4];
+        master.read_exact(&mut echo).unwrap();
+        assert_eq!(&echo, b"\x00\xff\x7e\x07");
+
+        // As a Read, both directions are interleaved
+        master.write_all(b"\x7e\x08").unwrap();
+        let mut buffer = [0u8; 4];
+        traced.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"\x7e\x08\x7e\x08");
+
+        // Closing the pty ends the dummy process and the trace
+        drop(master);
+        assert!(traced.next().is_none());
+        let mut status = 0;
+        unsafe { waitpid(pid, &mut status, 0) };
+    }
+}
@@ -7,10 +7,14 @@ use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 use pv::application::PacketType;
 use zerocopy::byteorder::big_endian::U16;
 
+#[cfg(feature = "transmit")]
+mod poller;
 mod receiver;
 use crate::gateway::link::{Address, GatewayID};
 use crate::pv;
 use crate::pv::link::SlotCounter;
+#[cfg(feature = "transmit")]
+pub use poller::{PollConfig, PollCounters, Poller};
 pub use receiver::{Counters, Receiver, Sink};
 
 #[derive(
@@ -0,0 +1,440 @@
+use super::{ReceiveRequest, ReceiveResponse};
+use crate::gateway::link::{
+    self, Address, Frame, FrameRef, GatewayID, SendError, Sink as _, TransmitConfig, Transmitter,
+};
+use crate::gateway::physical::ReadWriteConnection;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+use zerocopy::byteorder::big_endian::U16;
+use zerocopy::IntoBytes;
+
+/// How a `Poller` polls the gateways.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PollConfig {
+    /// The gateways to poll.
+    pub gateways: Vec<GatewayID>,
+    /// How often to poll each gateway.
+    pub interval: Duration,
+    /// How long to wait for a gateway to respond to a receive request.
+    pub response_timeout: Duration,
+    /// How to share the bus while sending receive requests.
+    pub transmit: TransmitConfig,
+}
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        Self {
+            gateways: Vec::new(),
+            interval: Duration::from_secs(1),
+            response_timeout: Duration::from_millis(100),
+            transmit: TransmitConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct PollCounters {
+    /// The number of receive requests sent.
+    pub requests: u64,
+    /// The number of receive responses received in reply.
+    pub responses: u64,
+    /// The number of receive requests which went unanswered.
+    pub no_responses: u64,
+    /// The number of receive responses which couldn't be interpreted.
+    pub invalid_responses: u64,
+    /// The number of receive requests which couldn't be sent, because the bus was busy, or because
+    /// they collided with another transmission.
+    pub send_errors: u64,
+}
+
+/// The callback through which the `Transmitter` feeds what it reads into the `Receiver`.
+type Received = Box<dyn FnMut(&[u8])>;
+
+/// A poller acting as the controller, asking gateways for the PV packets they have received.
+///
+/// The poller sends a receive request to each gateway in turn, and waits for its receive response.
+/// It requests the packet number of the previous response, as `Receiver` infers a controller
+/// does, so a gateway moves on to its next packets once it knows the previous ones arrived, and
+/// retransmits them otherwise.
+///
+/// Everything read from the bus is decoded by a link layer `Receiver` into `sink`, which would
+/// typically be a transport layer `Receiver`. Requests which the connection doesn't echo are
+/// passed to `sink` as they are sent, so that it sees them before their responses.
+pub struct Poller<C: ReadWriteConnection, S: link::Sink + 'static> {
+    tx: Transmitter<C, Received>,
+    rx: Rc<RefCell<link::Receiver<Tap<S>>>>,
+    config: PollConfig,
+    packet_numbers: BTreeMap<GatewayID, u16>,
+    counters: PollCounters,
+}
+
+impl<C: ReadWriteConnection, S: link::Sink + 'static> Poller<C, S> {
+    /// Poll over `conn`, passing everything received to `sink`.
+    ///
+    /// Fails if `conn` doesn't support read timeouts, as for `Transmitter`.
+    pub fn new(conn: C, config: PollConfig, sink: S) -> std::io::Result<Self> {
+        let rx = Rc::new(RefCell::new(link::Receiver::new(Tap {
+            sink,
+            awaiting: None,
+            response: None,
+        })));
+        let received: Received = {
+            let rx = rx.clone();
+            Box::new(move |bytes| rx.borrow_mut().extend_from_slice(bytes))
+        };
+        let tx = Transmitter::with_received(conn, config.transmit, received)?;
+
+        Ok(Self {
+            tx,
+            rx,
+            config,
+            packet_numbers: Default::default(),
+            counters: Default::default(),
+        })
+    }
+
+    pub fn config(&self) -> &PollConfig {
+        &self.config
+    }
+
+    /// Access the `Sink`.
+    pub fn sink(&self) -> Ref<'_, S> {
+        Ref::map(self.rx.borrow(), |rx| &rx.sink().sink)
+    }
+
+    /// Mutably access the `Sink`.
+    pub fn sink_mut(&mut self) -> RefMut<'_, S> {
+        RefMut::map(self.rx.borrow_mut(), |rx| &mut rx.sink_mut().sink)
+    }
+
+    /// Retrieve the counters of the link layer `Receiver` decoding the bus.
+    pub fn link_counters(&self) -> link::Counters {
+        *self.rx.borrow().counters()
+    }
+
+    /// Retrieve the current counters describing the poller's activity.
+    pub fn counters(&self) -> &PollCounters {
+        &self.counters
+    }
+
+    /// The packet number the next receive request to `gateway_id` will ask for.
+    pub fn packet_number(&self, gateway_id: GatewayID) -> u16 {
+        self.packet_numbers.get(&gateway_id).copied().unwrap_or(0)
+    }
+
+    /// Destroy the poller to obtain the connection and the `Sink`.
+    pub fn into_inner(self) -> (C, S) {
+        let conn = self.tx.into_inner();
+        // The transmitter held the only other reference
+        let rx = Rc::into_inner(self.rx).unwrap();
+        (conn, rx.into_inner().into_inner().sink)
+    }
+
+    /// Send a receive request to `gateway_id` and wait for its response.
+    ///
+    /// Returns `Ok(None)` if the gateway didn't respond in time, or responded with something which
+    /// couldn't be interpreted, in which case the next request will ask for the same packets.
+    pub fn poll(&mut self, gateway_id: GatewayID) -> Result<Option<ReceiveResponse>, SendError> {
+        let packet_number = self.packet_number(gateway_id);
+        let request = Frame {
+            address: Address::To(gateway_id),
+            frame_type: link::Type::RECEIVE_REQUEST,
+            payload: ReceiveRequest {
+                unknown_1: [0x00, 0x01],
+                packet_number: U16::new(packet_number),
+                unknown_2: 0x04,
+            }
+            .as_bytes()
+            .to_vec(),
+        };
+
+        self.rx.borrow_mut().sink_mut().awaiting = Some(gateway_id);
+        let sent = match self.tx.send(&request) {
+            Ok(sent) => sent,
+            Err(e) => {
+                self.rx.borrow_mut().sink_mut().awaiting = None;
+                if !matches!(e, SendError::Io(_)) {
+                    self.counters.send_errors += 1;
+                }
+                return Err(e);
+            }
+        };
+        self.counters.requests += 1;
+
+        if !sent.echoed {
+            // Show the sink the request, as if it had been read back
+            self.rx.borrow_mut().sink_mut().frame(FrameRef {
+                address: request.address,
+                frame_type: request.frame_type,
+                payload: &request.payload,
+                received: SystemTime::now(),
+            });
+        }
+
+        let start = Instant::now();
+        let response = loop {
+            if let Some(response) = self.rx.borrow_mut().sink_mut().response.take() {
+                break Some(response);
+            }
+            if start.elapsed() >= self.config.response_timeout {
+                break None;
+            }
+            self.tx.receive()?;
+        };
+        self.rx.borrow_mut().sink_mut().awaiting = None;
+
+        let Some(response) = response else {
+            self.counters.no_responses += 1;
+            return Ok(None);
+        };
+        let Ok((status, _)) = ReceiveResponse::read_from_bytes(&response, packet_number) else {
+            self.counters.invalid_responses += 1;
+            return Ok(None);
+        };
+        self.counters.responses += 1;
+
+        // Acknowledge the packets by asking for the next ones
+        self.packet_numbers.insert(gateway_id, status.packet_number);
+        Ok(Some(status))
+    }
+
+    /// Poll each gateway in turn, every `interval`, for as long as `keep_going` returns `true`.
+    ///
+    /// Gateways which don't respond, and requests which can't be sent because the bus is busy, are
+    /// retried in the next round. Fails on I/O errors.
+    pub fn run<F: FnMut() -> bool>(&mut self, mut keep_going: F) -> std::io::Result<()> {
+        while keep_going() {
+            let start = Instant::now();
+            for gateway_id in self.config.gateways.clone() {
+                match self.poll(gateway_id) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => log::debug!("no receive response from {:?}", gateway_id),
+                    Err(SendError::Io(e)) => return Err(e),
+                    Err(e) => log::warn!("error polling {:?}: {}", gateway_id, e),
+                }
+                if !keep_going() {
+                    return Ok(());
+                }
+            }
+
+            // Keep listening until the next round
+            while start.elapsed() < self.config.interval {
+                if !keep_going() {
+                    return Ok(());
+                }
+                self.tx.receive()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `link::Sink` passing frames on to another, keeping the receive response being waited for.
+struct Tap<S: link::Sink> {
+    sink: S,
+    awaiting: Option<GatewayID>,
+    response: Option<Vec<u8>>,
+}
+
+impl<S: link::Sink> link::Sink for Tap<S> {
+    fn frame(&mut self, frame: FrameRef<'_>) {
+        if let Some(gateway_id) = self.awaiting {
+            if frame.frame_type == link::Type::RECEIVE_RESPONSE
+                && frame.address == Address::From(gateway_id)
+            {
+                self.response = Some(frame.payload.to_vec());
+            }
+        }
+        self.sink.frame(frame);
+    }
+
+    fn error(&mut self, kind: link::ErrorKind, raw: &[u8]) {
+        self.sink.error(kind, raw);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TcpKeepaliveConfig;
+    use crate::gateway::physical::tcp;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use zerocopy::FromBytes;
+
+    /// Connect to a simulated gateway, handled by `gateway` on its own thread.
+    fn connect<G>(gateway: G) -> tcp::Connection
+    where
+        G: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || gateway(listener.accept().unwrap().0));
+
+        let keepalive = TcpKeepaliveConfig {
+            idle: Duration::from_secs(30),
+            interval: Duration::from_secs(10),
+            count: 5,
+        };
+        tcp::Connection::connect(addr, false, keepalive).unwrap()
+    }
+
+    /// A gateway with ID 0x1201 holding `packets`, one per packet number from 1, which answers
+    /// receive requests with the packets after the requested packet number. It reports the packet
+    /// numbers requested on `requests`.
+    fn gateway(packets: Vec<Vec<u8>>, requests: mpsc::Sender<u16>) -> impl FnOnce(TcpStream) {
+        move |mut stream| {
+            let mut rx = link::Receiver::new(Vec::<Frame>::new());
+            let mut buffer = [0u8; 64];
+            while let Ok(n @ 1..) = stream.read(&mut buffer) {
+                rx.extend_from_slice(&buffer[..n]);
+                for frame in std::mem::take(rx.sink_mut()) {
+                    if frame.address != Address::To(GatewayID::try_from(0x1201).unwrap()) {
+                        continue;
+                    }
+                    let request = ReceiveRequest::ref_from_bytes(&frame.payload).unwrap();
+                    let requested = request.packet_number.get();
+                    requests.send(requested).unwrap();
+
+                    let latest = packets.len() as u16;
+                    let mut payload = vec![0x00, 0xEF, (latest >> 8) as u8, latest as u8, 0, 0];
+                    for packet in packets.iter().skip(requested as usize) {
+                        payload.extend_from_slice(packet);
+                    }
+                    let response = Frame {
+                        address: Address::From(GatewayID::try_from(0x1201).unwrap()),
+                        frame_type: link::Type::RECEIVE_RESPONSE,
+                        payload,
+                    };
+                    stream.write_all(&response.encode()).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn poll() {
+        let packets = vec![
+            vec![0x31, 0x00, 0x09, 0x00, 0x09, 0x01, 0x01, 0xAA],
+            vec![0x31, 0x00, 0x0A, 0x00, 0x0A, 0x01, 0x01, 0xBB],
+        ];
+        let (requests_tx, requests) = mpsc::channel();
+        let config = PollConfig {
+            gateways: vec![
+                GatewayID::try_from(0x1201).unwrap(),
+                GatewayID::try_from(0x1202).unwrap(),
+            ],
+            response_timeout: Duration::from_millis(500),
+            transmit: TransmitConfig {
+                idle_gap: Duration::from_millis(20),
+                echo_timeout: None,
+                ..TransmitConfig::default()
+            },
+            ..PollConfig::default()
+        };
+        let conn = connect(gateway(packets.clone(), requests_tx));
+        let mut poller = Poller::new(conn, config, Vec::<Frame>::new()).unwrap();
+
+        let gateway_id = GatewayID::try_from(0x1201).unwrap();
+        let status = poller.poll(gateway_id).unwrap().unwrap();
+        assert_eq!(status.packet_number, 2);
+        assert_eq!(poller.packet_number(gateway_id), 2);
+
+        // The next request acknowledges both packets
+        let status = poller.poll(gateway_id).unwrap().unwrap();
+        assert_eq!(status.packet_number, 2);
+        assert_eq!(requests.try_iter().collect::<Vec<_>>(), vec![0, 2]);
+
+        // Nobody answers for 0x1202
+        let other = GatewayID::try_from(0x1202).unwrap();
+        assert_eq!(poller.poll(other).unwrap(), None);
+        assert_eq!(poller.packet_number(other), 0);
+
+        assert_eq!(
+            poller.counters(),
+            &PollCounters {
+                requests: 3,
+                responses: 2,
+                no_responses: 1,
+                ..Default::default()
+            }
+        );
+
+        // The sink saw each request, unechoed, before its response
+        let frames = poller.sink();
+        let types: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.address, frame.frame_type))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                (Address::To(gateway_id), link::Type::RECEIVE_REQUEST),
+                (Address::From(gateway_id), link::Type::RECEIVE_RESPONSE),
+                (Address::To(gateway_id), link::Type::RECEIVE_REQUEST),
+                (Address::From(gateway_id), link::Type::RECEIVE_RESPONSE),
+                (Address::To(other), link::Type::RECEIVE_REQUEST),
+            ]
+        );
+        assert!(frames[1].payload.ends_with(&packets.concat()));
+    }
+
+    #[test]
+    fn run() {
+        let (requests_tx, requests) = mpsc::channel();
+        let config = PollConfig {
+            gateways: vec![GatewayID::try_from(0x1201).unwrap()],
+            interval: Duration::from_millis(50),
+            response_timeout: Duration::from_millis(500),
+            transmit: TransmitConfig {
+                idle_gap: Duration::from_millis(10),
+                echo_timeout: None,
+                ..TransmitConfig::default()
+            },
+        };
+        let conn = connect(gateway(vec![vec![0x31, 0, 9, 0, 9, 1, 0]], requests_tx));
+        let mut poller = Poller::new(conn, config, Vec::<Frame>::new()).unwrap();
+
+        // Stop once the gateway has answered three requests
+        let mut requested = Vec::new();
+        poller
+            .run(|| {
+                requested.extend(requests.try_iter());
+                requested.len() < 3
+            })
+            .unwrap();
+
+        assert_eq!(requested, vec![0, 1, 1]);
+        assert_eq!(poller.counters().responses, 3);
+        let (_, frames) = poller.into_inner();
+        assert_eq!(frames.len(), 6);
+    }
+
+    #[test]
+    fn run_stops_between_rounds() {
+        let (requests_tx, requests) = mpsc::channel();
+        let config = PollConfig {
+            gateways: vec![GatewayID::try_from(0x1201).unwrap()],
+            interval: Duration::from_secs(60),
+            response_timeout: Duration::from_millis(500),
+            transmit: TransmitConfig {
+                idle_gap: Duration::from_millis(10),
+                echo_timeout: None,
+                ..TransmitConfig::default()
+            },
+        };
+        let conn = connect(gateway(vec![], requests_tx));
+        let mut poller = Poller::new(conn, config, Vec::<Frame>::new()).unwrap();
+
+        // Asked to stop while waiting for the next round, the poller doesn't wait it out
+        let start = Instant::now();
+        poller
+            .run(|| start.elapsed() < Duration::from_millis(200))
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(requests.try_iter().collect::<Vec<_>>(), vec![0]);
+    }
+}
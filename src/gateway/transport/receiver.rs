@@ -1,40 +1,61 @@
-use super::super::link::{self, Frame, GatewayID};
+use super::super::link::{self, FrameRef, GatewayID};
 use super::*;
 use crate::gateway::link::Address;
 use crate::pv;
 use crate::pv::link::SlotCounter;
-use crate::pv::network::ReceivedPacketHeader;
+use crate::pv::network::{ReceivedPacketHeader, ReceivedPackets};
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::mem::size_of;
+use std::time::SystemTime;
 
+/// An object which handles reception callbacks.
+///
+/// Each callback is passed the `timestamp` of the frame it stems from, as received by the link
+/// layer `Receiver`. For command executions, that is the frame carrying the response.
 pub trait Sink {
     /// Enumeration started, using the indicated gateway ID.
-    fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID);
+    fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID, timestamp: SystemTime);
 
     /// A gateway's address was observed.
     ///
     /// If the network is enumerating, the gateway ID may be the `enumeration_gateway_id`, in which
     /// case this ID may not be unique.
-    fn gateway_identity_observed(&mut self, gateway_id: GatewayID, address: pv::LongAddress);
+    fn gateway_identity_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        address: pv::LongAddress,
+        timestamp: SystemTime,
+    );
 
     /// A gateway's version was observed.
-    fn gateway_version_observed(&mut self, gateway_id: GatewayID, version: &str);
+    fn gateway_version_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        version: &str,
+        timestamp: SystemTime,
+    );
 
     /// Enumeration ended.
-    fn enumeration_ended(&mut self, gateway_id: GatewayID);
+    fn enumeration_ended(&mut self, gateway_id: GatewayID, timestamp: SystemTime);
 
-    /// A gateway's slot counter was captured inside the gateway.
+    /// A gateway's slot counter was captured inside the gateway, as it processed the receive
+    /// request received at `timestamp`.
     ///
     /// The value of the slot counter at this moment may be described by a subsequent call to
     /// `gateway_slot_counter_observed()`.
-    fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID);
+    fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID, timestamp: SystemTime);
 
     /// A gateway's slot counter was observed.
     ///
     /// The indicated slot counter value corresponds to the moment when the counter was most
     /// recently captured by the gateway, which occurred 4 to 50+ milliseconds ago.
-    fn gateway_slot_counter_observed(&mut self, gateway_id: GatewayID, slot_counter: SlotCounter);
+    fn gateway_slot_counter_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        slot_counter: SlotCounter,
+        timestamp: SystemTime,
+    );
 
     /// A PV network packet was received from a gateway.
     fn packet_received(
@@ -42,14 +63,31 @@ pub trait Sink {
         gateway_id: GatewayID,
         header: &ReceivedPacketHeader,
         data: &[u8],
+        timestamp: SystemTime,
     );
 
+    /// A gateway retransmitted a receive response, repeating `packets` which were already passed
+    /// to `packet_received()`.
+    ///
+    /// The repeated packets are not passed to `packet_received()` again, though any new packets
+    /// following them in the same response are. Retransmissions are counted in the `Receiver`'s
+    /// `Counters` either way. By default, they are otherwise ignored.
+    fn receive_response_retransmitted(
+        &mut self,
+        gateway_id: GatewayID,
+        packets: ReceivedPackets<'_>,
+        timestamp: SystemTime,
+    ) {
+        let _ = (gateway_id, packets, timestamp);
+    }
+
     /// A command was executed by a gateway.
     fn command_executed(
         &mut self,
         gateway_id: GatewayID,
         request: (PacketType, &[u8]),
         response: (PacketType, &[u8]),
+        timestamp: SystemTime,
     );
 }
 
@@ -57,13 +95,16 @@ pub trait Sink {
 pub struct Receiver<S: Sink> {
     sink: S,
     rx_packet_numbers: BTreeMap<GatewayID, u16>,
+    /// The packets of the latest receive response from each gateway, with the packet number which
+    /// was requested.
+    rx_packets: BTreeMap<GatewayID, (u16, Vec<u8>)>,
     command_sequence_numbers: BTreeMap<GatewayID, CommandSequenceNumber>,
     commands_awaiting_response: BTreeMap<(GatewayID, CommandSequenceNumber), (PacketType, Vec<u8>)>,
     counters: Counters,
 }
 
 impl<S: Sink> link::Sink for Receiver<S> {
-    fn frame(&mut self, frame: Frame) {
+    fn frame(&mut self, frame: FrameRef<'_>) {
         match frame.frame_type {
             link::Type::RECEIVE_REQUEST => {
                 self.receive_request(frame);
@@ -119,7 +160,7 @@ impl<S: Sink> link::Sink for Receiver<S> {
             link::Type::ENUMERATION_END_RESPONSE => match frame.address {
                 Address::From(gateway) => {
                     self.counters.enumeration_end_responses += 1;
-                    self.sink.enumeration_ended(gateway);
+                    self.sink.enumeration_ended(gateway, frame.received);
                 }
                 Address::To(_) => {
                     self.counters.invalid_enumeration_end_responses += 1;
@@ -138,6 +179,7 @@ impl<S: Sink> Receiver<S> {
         Self {
             sink,
             rx_packet_numbers: Default::default(),
+            rx_packets: Default::default(),
             command_sequence_numbers: Default::default(),
             commands_awaiting_response: Default::default(),
             counters: Default::default(),
@@ -169,20 +211,21 @@ impl<S: Sink> Receiver<S> {
         self.counters = Default::default();
     }
 
-    fn receive_request(&mut self, frame: Frame) {
+    fn receive_request(&mut self, frame: FrameRef<'_>) {
         let Address::To(gateway_id) = frame.address else {
             self.counters.invalid_receive_request += 1;
             return;
         };
 
-        let Ok(payload) = ReceiveRequest::ref_from_bytes(frame.payload.as_ref()) else {
+        let Ok(payload) = ReceiveRequest::ref_from_bytes(frame.payload) else {
             self.counters.invalid_receive_request += 1;
             return;
         };
 
         // Indicate that the gateway captured its slot counter now, while processing the receive
         // request
-        self.sink.gateway_slot_counter_captured(gateway_id);
+        self.sink
+            .gateway_slot_counter_captured(gateway_id, frame.received);
 
         self.counters.receive_requests += 1;
 
@@ -191,7 +234,7 @@ impl<S: Sink> Receiver<S> {
         *self.rx_packet_numbers.entry(gateway_id).or_insert(n) = n;
     }
 
-    fn receive_response(&mut self, frame: Frame) {
+    fn receive_response(&mut self, frame: FrameRef<'_>) {
         let Address::From(gateway_id) = frame.address else {
             self.counters.invalid_receive_responses += 1;
             return;
@@ -204,36 +247,64 @@ impl<S: Sink> Receiver<S> {
         };
 
         // Interpret the response
-        let Ok((status, packets)) = ReceiveResponse::read_from_bytes(frame.payload.as_ref(), *n)
-        else {
+        let Ok((status, packets)) = ReceiveResponse::read_from_bytes(frame.payload, *n) else {
             self.counters.invalid_receive_responses += 1;
             return;
         };
 
         self.counters.receive_responses += 1;
 
-        // TODO: deduplicate gateway -> controller retransmissions
+        // If the controller missed a response, it requests the same packet number again, and the
+        // gateway retransmits the same packets, possibly followed by packets received since.
+        // Packets are whole, so if the previous response's packets are a prefix of these, they
+        // are the same packets.
+        let requested = *n;
+        let repeated = match self.rx_packets.get(&gateway_id) {
+            Some((previous_request, previous))
+                if *previous_request == requested
+                    && !previous.is_empty()
+                    && packets.0.starts_with(previous) =>
+            {
+                previous.len()
+            }
+            _ => 0,
+        };
+        self.rx_packets
+            .insert(gateway_id, (requested, packets.0.to_vec()));
 
         // Update the packet number
         *n = status.packet_number;
 
         // Observe the slot counter
         self.sink
-            .gateway_slot_counter_observed(gateway_id, status.slot_counter);
+            .gateway_slot_counter_observed(gateway_id, status.slot_counter, frame.received);
+
+        // Suppress the repeated packets
+        let (repeated, packets) = packets.0.split_at(repeated);
+        if !repeated.is_empty() {
+            self.counters.retransmitted_receive_responses += 1;
+            self.counters.retransmitted_receive_packets += ReceivedPackets(repeated).count() as u64;
+            self.sink.receive_response_retransmitted(
+                gateway_id,
+                ReceivedPackets(repeated),
+                frame.received,
+            );
+        }
 
-        for packet in packets {
+        for packet in ReceivedPackets(packets) {
             if let Ok((header, data)) = packet {
                 self.counters.receive_packets += 1;
 
                 // Observe the packet
-                self.sink.packet_received(gateway_id, header, data);
+                self.sink
+                    .packet_received(gateway_id, header, data, frame.received);
             } else {
                 self.counters.receive_packet_too_short += 1;
             }
         }
     }
 
-    fn command_request(&mut self, frame: Frame) {
+    fn command_request(&mut self, frame: FrameRef<'_>) {
         let Address::To(gateway_id) = frame.address else {
             println!("bad tx request: {:?}", frame);
             self.counters.invalid_command_requests += 1;
@@ -276,7 +347,7 @@ impl<S: Sink> Receiver<S> {
         }
     }
 
-    fn command_response(&mut self, frame: Frame) {
+    fn command_response(&mut self, frame: FrameRef<'_>) {
         let Address::From(gateway_id) = frame.address else {
             println!("wrong addr: {:?}", frame);
             self.counters.invalid_command_responses += 1;
@@ -307,16 +378,17 @@ impl<S: Sink> Receiver<S> {
             gateway_id,
             (request_packet_type, request_payload.as_slice()),
             (header.packet_type, payload),
+            frame.received,
         );
     }
 
-    fn enumeration_start_request(&mut self, frame: Frame) {
+    fn enumeration_start_request(&mut self, frame: FrameRef<'_>) {
         let Address::To(GatewayID::ZERO) = frame.address else {
             self.counters.invalid_enumeration_start_request += 1;
             return;
         };
 
-        let Ok(request) = EnumerationStartRequest::ref_from_bytes(frame.payload.as_ref()) else {
+        let Ok(request) = EnumerationStartRequest::ref_from_bytes(frame.payload) else {
             self.counters.invalid_enumeration_start_request += 1;
             return;
         };
@@ -328,16 +400,16 @@ impl<S: Sink> Receiver<S> {
 
         self.counters.enumeration_start_requests += 1;
 
-        self.sink.enumeration_started(gateway_id);
+        self.sink.enumeration_started(gateway_id, frame.received);
     }
 
-    fn identify_response(&mut self, frame: Frame) {
+    fn identify_response(&mut self, frame: FrameRef<'_>) {
         let Address::From(gateway_id) = frame.address else {
             self.counters.invalid_identify_responses += 1;
             return;
         };
 
-        let Ok(response) = IdentifyResponse::ref_from_bytes(frame.payload.as_ref()) else {
+        let Ok(response) = IdentifyResponse::ref_from_bytes(frame.payload) else {
             self.counters.invalid_identify_responses += 1;
             return;
         };
@@ -345,16 +417,16 @@ impl<S: Sink> Receiver<S> {
         self.counters.identify_responses += 1;
 
         self.sink
-            .gateway_identity_observed(gateway_id, response.pv_long_address);
+            .gateway_identity_observed(gateway_id, response.pv_long_address, frame.received);
     }
 
-    fn enumeration_response(&mut self, frame: Frame) {
+    fn enumeration_response(&mut self, frame: FrameRef<'_>) {
         let Address::From(gateway_id) = frame.address else {
             self.counters.invalid_enumeration_responses += 1;
             return;
         };
 
-        let Ok(response) = IdentifyResponse::ref_from_bytes(frame.payload.as_ref()) else {
+        let Ok(response) = IdentifyResponse::ref_from_bytes(frame.payload) else {
             self.counters.invalid_enumeration_responses += 1;
             return;
         };
@@ -362,16 +434,16 @@ impl<S: Sink> Receiver<S> {
         self.counters.enumeration_responses += 1;
 
         self.sink
-            .gateway_identity_observed(gateway_id, response.pv_long_address);
+            .gateway_identity_observed(gateway_id, response.pv_long_address, frame.received);
     }
 
-    pub fn version_response(&mut self, frame: Frame) {
+    pub fn version_response(&mut self, frame: FrameRef<'_>) {
         let Address::From(gateway_id) = frame.address else {
             self.counters.invalid_version_responses += 1;
             return;
         };
 
-        let version = match std::str::from_utf8(frame.payload.as_ref()) {
+        let version = match std::str::from_utf8(frame.payload) {
             Ok(str) if !str.is_empty() => str,
             _ => {
                 self.counters.invalid_version_responses += 1;
@@ -380,7 +452,8 @@ impl<S: Sink> Receiver<S> {
         };
 
         self.counters.version_responses += 1;
-        self.sink.gateway_version_observed(gateway_id, version);
+        self.sink
+            .gateway_version_observed(gateway_id, version, frame.received);
     }
 }
 
@@ -395,6 +468,10 @@ pub struct Counters {
     pub receive_responses: u64,
     pub receive_packets: u64,
     pub receive_packet_too_short: u64,
+    /// The number of receive responses repeating packets from the previous response.
+    pub retransmitted_receive_responses: u64,
+    /// The number of packets suppressed as repeats from a previous response.
+    pub retransmitted_receive_packets: u64,
     pub invalid_command_requests: u64,
     pub retransmitted_command_requests: u64,
     pub command_requests: u64,
@@ -457,6 +534,10 @@ mod tests {
             header: ReceivedPacketHeader,
             data: Vec<u8>,
         },
+        ReceiveResponseRetransmitted {
+            gateway_id: GatewayID,
+            packets: Vec<u8>,
+        },
         CommandExecuted {
             gateway_id: GatewayID,
             request: (PacketType, Vec<u8>),
@@ -465,34 +546,54 @@ mod tests {
     }
     use Event::*;
 
+    /// A sink keeping events, and separately their timestamps.
     #[derive(Debug, Default)]
-    struct TestSink(Vec<Event>);
+    struct TestSink(Vec<Event>, Vec<SystemTime>);
     impl super::Sink for TestSink {
-        fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID) {
+        fn enumeration_started(
+            &mut self,
+            enumeration_gateway_id: GatewayID,
+            timestamp: SystemTime,
+        ) {
+            self.1.push(timestamp);
             self.0.push(EnumerationStarted {
                 enumeration_gateway_id,
             });
         }
 
-        fn gateway_identity_observed(&mut self, gateway_id: GatewayID, address: LongAddress) {
+        fn gateway_identity_observed(
+            &mut self,
+            gateway_id: GatewayID,
+            address: LongAddress,
+            timestamp: SystemTime,
+        ) {
+            self.1.push(timestamp);
             self.0.push(GatewayIdentityObserved {
                 gateway_id,
                 address,
             })
         }
 
-        fn gateway_version_observed(&mut self, gateway_id: GatewayID, version: &str) {
+        fn gateway_version_observed(
+            &mut self,
+            gateway_id: GatewayID,
+            version: &str,
+            timestamp: SystemTime,
+        ) {
+            self.1.push(timestamp);
             self.0.push(GatewayVersionObserved {
                 gateway_id,
                 version: version.into(),
             })
         }
 
-        fn enumeration_ended(&mut self, gateway_id: GatewayID) {
+        fn enumeration_ended(&mut self, gateway_id: GatewayID, timestamp: SystemTime) {
+            self.1.push(timestamp);
             self.0.push(EnumerationEnded { gateway_id });
         }
 
-        fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID) {
+        fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID, timestamp: SystemTime) {
+            self.1.push(timestamp);
             self.0.push(GatewaySlotCounterCaptured { gateway_id });
         }
 
@@ -500,7 +601,9 @@ mod tests {
             &mut self,
             gateway_id: GatewayID,
             slot_counter: SlotCounter,
+            timestamp: SystemTime,
         ) {
+            self.1.push(timestamp);
             self.0.push(GatewaySlotCounterObserved {
                 gateway_id,
                 slot_counter,
@@ -512,20 +615,37 @@ mod tests {
             gateway_id: GatewayID,
             header: &ReceivedPacketHeader,
             data: &[u8],
+            timestamp: SystemTime,
         ) {
+            self.1.push(timestamp);
             self.0.push(PacketReceived {
                 gateway_id,
-                header: header.clone(),
+                header: *header,
                 data: data.into(),
             })
         }
 
+        fn receive_response_retransmitted(
+            &mut self,
+            gateway_id: GatewayID,
+            packets: ReceivedPackets<'_>,
+            timestamp: SystemTime,
+        ) {
+            self.1.push(timestamp);
+            self.0.push(ReceiveResponseRetransmitted {
+                gateway_id,
+                packets: packets.0.into(),
+            })
+        }
+
         fn command_executed(
             &mut self,
             gateway_id: GatewayID,
             request: (PacketType, &[u8]),
             response: (PacketType, &[u8]),
+            timestamp: SystemTime,
         ) {
+            self.1.push(timestamp);
             self.0.push(CommandExecuted {
                 gateway_id,
                 request: (request.0, request.1.into()),
@@ -537,10 +657,11 @@ mod tests {
     #[test]
     fn unhandled_frame_type() {
         let mut rx = Receiver::new(TestSink::default());
-        rx.frame(Frame {
+        rx.frame(FrameRef {
             address: 0x1201.into(),
             frame_type: Type(0xffff),
-            payload: vec![],
+            payload: &[],
+            received: SystemTime::UNIX_EPOCH,
         });
 
         assert_eq!(&rx.sink().0, &[]);
@@ -559,10 +680,11 @@ mod tests {
 
         assert_eq!(rx.counters(), &Counters::default());
 
-        rx.frame(Frame {
+        rx.frame(FrameRef {
             address: 0x1201.into(),
             frame_type: Type(0xffff),
-            payload: vec![],
+            payload: &[],
+            received: SystemTime::UNIX_EPOCH,
         });
         assert_ne!(rx.counters(), &Counters::default());
 
@@ -642,4 +764,127 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn timestamps() {
+        let t = |secs| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+
+        let mut rx = gateway::link::Receiver::new(Receiver::new(TestSink::default()));
+        rx.extend_from_slice_at(
+            &[
+                0x00, 0xFF, 0xFF, 0x7E, 0x07, 0x12, 0x01, 0x01, 0x48, 0x00, 0x01, 0x18, 0x83, 0x04,
+                0x17, 0x44, 0x7E, 0x08, 0xFF, 0x7E, 0x07, 0x92, 0x01, 0x01,
+            ],
+            t(1),
+        );
+        rx.extend_from_slice_at(
+            &[
+                0x49, 0x00, 0xFF, 0x7C, 0xDB, 0xC2, 0x7E, 0x05, 0x85, 0x7E, 0x08,
+            ],
+            t(2),
+        );
+
+        // Each event is timestamped with the slice which completed its frame
+        assert_eq!(
+            &rx.sink().sink().0,
+            &[
+                GatewaySlotCounterCaptured {
+                    gateway_id: GatewayID::try_from(0x1201).unwrap()
+                },
+                GatewaySlotCounterObserved {
+                    gateway_id: GatewayID::try_from(0x1201).unwrap(),
+                    slot_counter: SlotCounter::from(0xDBC2),
+                },
+            ]
+        );
+        assert_eq!(&rx.sink().sink().1, &[t(1), t(2)]);
+    }
+
+    #[test]
+    fn retransmitted_receive_responses() {
+        let gateway_id = GatewayID::try_from(0x1201).unwrap();
+        let request = |packet_number: u8| {
+            gateway::Frame {
+                address: Address::To(gateway_id),
+                frame_type: Type::RECEIVE_REQUEST,
+                payload: vec![0x00, 0x01, 0x00, packet_number, 0x04],
+            }
+            .encode()
+        };
+        let response = |packet_number: u8, packets: &[&[u8]]| {
+            gateway::Frame {
+                address: Address::From(gateway_id),
+                frame_type: Type::RECEIVE_RESPONSE,
+                payload: [
+                    &[0x00, 0xFF, packet_number, 0xDB, 0xC2],
+                    packets.concat().as_slice(),
+                ]
+                .concat(),
+            }
+            .encode()
+        };
+        let a: &[u8] = &[0x31, 0x00, 0x09, 0x00, 0x09, 0x01, 0x01, 0xAA];
+        let b: &[u8] = &[0x31, 0x00, 0x0A, 0x00, 0x0A, 0x01, 0x01, 0xBB];
+        let c: &[u8] = &[0x31, 0x00, 0x09, 0x00, 0x09, 0x02, 0x01, 0xCC];
+        let received = |packet: &[u8]| PacketReceived {
+            gateway_id,
+            header: *ReceivedPacketHeader::ref_from_bytes(&packet[..7]).unwrap(),
+            data: packet[7..].into(),
+        };
+
+        let mut rx = gateway::link::Receiver::new(Receiver::new(TestSink::default()));
+        for bytes in [
+            request(0x10),
+            response(0x11, &[a]),
+            // The controller missed the response, and asks again
+            request(0x10),
+            response(0x12, &[a, b]),
+            // The controller got it this time
+            request(0x12),
+            response(0x13, &[c]),
+            // Nothing new, and an empty response isn't a retransmission
+            request(0x13),
+            response(0x13, &[]),
+            request(0x13),
+            response(0x13, &[]),
+            // The same packets in reply to a different packet number aren't either
+            request(0x13),
+            response(0x14, &[c]),
+        ] {
+            rx.extend_from_slice(&bytes);
+        }
+
+        let events: Vec<_> = rx
+            .sink()
+            .sink()
+            .0
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    PacketReceived { .. } | ReceiveResponseRetransmitted { .. }
+                )
+            })
+            .cloned()
+            .collect();
+        assert_eq!(
+            events,
+            &[
+                received(a),
+                ReceiveResponseRetransmitted {
+                    gateway_id,
+                    packets: a.into(),
+                },
+                received(b),
+                received(c),
+                received(c),
+            ]
+        );
+
+        let counters = rx.sink().counters();
+        assert_eq!(counters.receive_responses, 6);
+        assert_eq!(counters.receive_packets, 4);
+        assert_eq!(counters.retransmitted_receive_responses, 1);
+        assert_eq!(counters.retransmitted_receive_packets, 1);
+    }
 }
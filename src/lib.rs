@@ -7,7 +7,11 @@ pub mod pv;
 pub mod capture;
 
 pub mod config;
+pub mod decode;
+pub mod detect;
 pub mod observer;
+pub mod pcap;
+pub mod serve;
 
 #[cfg(test)]
 pub mod test_data;
@@ -1,16 +1,19 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand};
 use log::LevelFilter;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
-use std::io::{ErrorKind, Read, Write};
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::thread::sleep;
-use std::time::{Duration, Instant};
-use taptap::gateway::{physical, Frame, GatewayID};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+use taptap::gateway::{physical, Frame, FrameRef, GatewayID};
 use taptap::pv::application::{NodeTableResponseEntry, PowerReport, TopologyReport};
-use taptap::pv::network::{NodeAddress, ReceivedPacketHeader};
+use taptap::pv::network::{NodeAddress, ReceivedPacketHeader, ReceivedPackets};
 use taptap::pv::{LongAddress, NodeID, PacketType, SlotCounter};
-use taptap::{config, gateway, pv};
+use taptap::{capture, config, decode, gateway, pv, serve};
 
 #[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
@@ -23,7 +26,7 @@ struct Cli {
 #[derive(Subcommand, Debug, Clone)]
 enum Commands {
     /// List the serial ports available on this system
-    #[cfg(feature = "serialport")]
+    #[cfg(any(feature = "serialport", unix))]
     ListSerialPorts,
 
     /// Observe the system, extracting data as it runs
@@ -34,6 +37,15 @@ enum Commands {
         /// Path of the JSON file to provide persistent storage for the infrastructure topology data
         #[arg(long, required = false, value_name = "FILE", default_value = Some(""))]
         persistent_file: String,
+
+        /// Path of a JSON file listing several sources to observe at once, instead of a single source
+        #[arg(
+            long,
+            group = "source",
+            conflicts_with_all = ["persistent_file", "serve_raw"],
+            value_name = "FILE"
+        )]
+        sources: Option<PathBuf>,
     },
 
     /// Peek at the raw data flowing at the gateway physical layer
@@ -49,6 +61,9 @@ enum Commands {
     PeekFrames {
         #[command(flatten)]
         source: Source,
+        /// Also print line errors, with the bytes which caused them
+        #[arg(long)]
+        show_errors: bool,
     },
 
     /// Peek at the gateway transport and PV application layer activity
@@ -56,166 +71,460 @@ enum Commands {
         #[command(flatten)]
         source: Source,
     },
+
+    /// Record the raw data flowing at the gateway physical layer to capture files
+    Record {
+        #[command(flatten)]
+        source: Source,
+
+        /// Path of the capture file to write (a start timestamp is added to the name when rotating)
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Start a new capture file once the current one reaches this size in bytes (0 for no limit)
+        #[arg(long, required = false, value_name = "BYTES", default_value = Some("0"))]
+        rotate_size: u64,
+
+        /// Start a new capture file after this many seconds (0 for no limit)
+        #[arg(long, required = false, value_name = "SECONDS", default_value = Some("0"))]
+        rotate_interval: u64,
+
+        /// The interval between flushes of the capture file to disk in seconds (0 to flush only on close)
+        #[arg(long, required = false, value_name = "SECONDS", default_value = Some("10"))]
+        flush_interval: u64,
+    },
+
+    /// Export the gateway link layer frames, from a capture file or a live source, to pcapng for Wireshark
+    ExportPcap {
+        #[command(flatten)]
+        source: Source,
+
+        /// Path of the pcapng file to write, or - for standard output
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Also write the raw bytes as received, as packets on a second interface
+        #[arg(long)]
+        raw_bytes: bool,
+    },
+
+    /// Inspect and manipulate capture files
+    #[command(subcommand)]
+    Capture(CaptureCommands),
+
+    /// Find which serial ports, and optionally TCP endpoints, see a live TAP bus
+    Detect {
+        /// Also probe this TCP endpoint (may be repeated)
+        #[arg(long, value_name = "HOST[:PORT]")]
+        tcp: Vec<String>,
+
+        /// Don't probe the serial ports on this system
+        #[arg(long)]
+        no_serial: bool,
+
+        /// How long to listen to each source in seconds
+        #[arg(long, required = false, value_name = "SECONDS", default_value = Some("5"))]
+        duration: u64,
+    },
+
+    /// Act as the controller, polling gateways for PV packets, and observe the system as it runs
+    #[cfg(feature = "transmit")]
+    // A capture can't be polled
+    #[command(
+        mut_arg("capture", |arg| arg.hide(true).conflicts_with("gateways")),
+        mut_arg("replay_speed", |arg| arg.hide(true).conflicts_with("gateways"))
+    )]
+    Poll {
+        #[command(flatten)]
+        source: Source,
+
+        /// The ID of a gateway to poll, e.g. 0x1201 (may be repeated)
+        #[arg(long = "gateway", required = true, value_name = "ID", value_parser = parse_gateway_id)]
+        gateways: Vec<GatewayID>,
+
+        /// The interval between polls of each gateway in milliseconds
+        #[arg(long, required = false, value_name = "MILLISECONDS", default_value = Some("1000"))]
+        poll_interval: u64,
+
+        /// Don't expect to read back what is sent, as with devices which don't echo their transmissions
+        #[arg(long)]
+        no_echo: bool,
+
+        /// Allow writing to --tcp and --listen sources, which are otherwise opened read-only and can't be polled
+        #[arg(long)]
+        read_write: bool,
+
+        /// Path of the JSON file to provide persistent storage for the infrastructure topology data
+        #[arg(long, required = false, value_name = "FILE", default_value = Some(""))]
+        persistent_file: String,
+    },
+
+    /// Decode a hex dump of bus traffic, breaking it down into frames, packets and their fields
+    Decode {
+        /// Bytes written as hex, such as `FF 7E 07 92 01 ...` (read from standard input if not given)
+        #[arg(value_name = "HEX", conflicts_with = "file")]
+        hex: Vec<String>,
+
+        /// Path of a text file containing the hex dump
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum CaptureCommands {
+    /// Describe capture files: their header, records, time span, bytes and decoded frames
+    Info {
+        /// Paths of the capture files
+        #[arg(required = true, value_name = "FILE")]
+        inputs: Vec<PathBuf>,
+    },
+
+    /// Copy the records of a capture file within a time range
+    Slice {
+        /// Path of the capture file
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Path of the capture file to write, or - for standard output
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Copy records received at or after this time, e.g. 2024-08-12T22:00:00Z
+        #[arg(long, value_name = "TIME")]
+        from: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Copy records received before this time, e.g. 2024-08-12T23:00:00Z
+        #[arg(long, value_name = "TIME")]
+        to: Option<chrono::DateTime<chrono::Utc>>,
+    },
+
+    /// Interleave the records of several capture files by timestamp
+    Merge {
+        /// Paths of the capture files
+        #[arg(required = true, value_name = "FILE")]
+        inputs: Vec<PathBuf>,
+
+        /// Path of the capture file to write, or - for standard output
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+    },
+
+    /// Concatenate the records of several capture files, one file after another
+    Cat {
+        /// Paths of the capture files
+        #[arg(required = true, value_name = "FILE")]
+        inputs: Vec<PathBuf>,
+
+        /// Path of the capture file to write, or - for standard output
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+    },
 }
 
 #[derive(Args, Debug, Clone)]
-#[group(id="mode", required = true, multiple = true, args=&["serial", "tcp"])]
+#[group(skip)]
+#[command(group = ArgGroup::new("source").required(true))]
 struct Source {
     /// The name of the serial port (try `taptap list-serial-ports`) of the Modbus-to-serial device (mutually exclusive to --tcp)
-    #[arg(
-        long,
-        required = true,
-        conflicts_with = "tcp",
-        value_name = "SERIAL-PORT"
-    )]
+    #[arg(long, groups = ["source", "serial_line"], value_name = "SERIAL-PORT")]
     #[cfg(feature = "serialport")]
     serial: Option<String>,
 
+    /// The path of a serial port device, e.g. /dev/ttyUSB0, opened directly with termios instead of the serialport library
+    #[arg(long, groups = ["source", "serial_line"], value_name = "PATH")]
+    #[cfg(unix)]
+    tty: Option<PathBuf>,
+
     /// The IP or hostname of the device which is providing Modbus-over-TCP service
+    #[arg(long, groups = ["source", "network"], value_name = "DESTINATION")]
+    tcp: Option<String>,
+
+    /// The local address and port on which to wait for a device to connect, for devices which can only connect out
+    #[arg(long, groups = ["source", "network"], value_name = "ADDRESS:PORT")]
+    listen: Option<String>,
+
+    /// The path of a capture file (see `taptap record`) to replay instead of a live connection
+    #[arg(long, group = "source", value_name = "FILE")]
+    capture: Option<PathBuf>,
+
+    /// Read raw bus bytes from standard input, e.g. piped from `socat` or `taptap peek-bytes --raw`
+    #[arg(long, groups = ["source", "stream"])]
+    stdin: bool,
+
+    /// Read raw bus bytes from a file or a named pipe
+    #[arg(long, groups = ["source", "stream"], value_name = "PATH")]
+    file: Option<PathBuf>,
+
+    /// Trace the serial port of a local process using ptrace, when running as root on the Tigo controller itself
     #[arg(
         long,
-        required = true,
-        conflicts_with = "serial",
-        value_name = "DESTINATION"
+        group = "source",
+        value_name = "PROCESS-NAME",
+        num_args = 0..=1,
+        default_missing_value = taptap::gateway::physical::trace_meshdcd::DEFAULT_PROCESS_NAME
     )]
-    tcp: Option<String>,
+    #[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+    trace_meshdcd: Option<String>,
+
+    /// If --stdin or --file is specified, wait for more data at the end of the input instead of exiting, like `tail -f`
+    #[arg(long, required = false, requires = "stream")]
+    follow: bool,
+
+    /// If --capture is specified, the replay speed relative to real time (0 for as fast as possible)
+    #[arg(long, required = false, requires = "capture", value_name = "FACTOR", default_value = Some("0"))]
+    replay_speed: f64,
 
     /// If --tcp is specified, the port to which to connect
-    #[arg(long, required = false, requires = "tcp", conflicts_with = "serial", value_name = "PORT NUMBER", default_value = Some("502"))]
+    #[arg(long, required = false, requires = "tcp", value_name = "PORT NUMBER", default_value = Some("502"))]
     port: u16,
 
     /// The time after which connection is re-established if no data is received in seconds (0 for no timeout)
     #[arg(long, required = false, value_name = "SECONDS", default_value = Some("60"))]
     reconnect_timeout: u64,
 
+    /// The time after which connection is re-established if data is received but no valid frames, in seconds (0 for no timeout)
+    #[arg(long, required = false, value_name = "SECONDS", default_value = Some("0"))]
+    frame_timeout: u64,
+
     /// The number of times to retry reconnecting before giving up (0 for infinite retries)
     #[arg(long, required = false, value_name = "INT", default_value = Some("0"))]
     reconnect_retry: u32,
 
-    /// The delay between reconnect attempts in seconds
+    /// The delay before the first reconnect attempt in seconds, doubling after each failed attempt
     #[arg(long, required = false, value_name = "SECONDS", default_value = Some("5"))]
     reconnect_delay: u64,
 
-    /// If --tcp is specified, the idle time in seconds before keepalive probes are sent
-    #[arg(long, required = false, requires = "tcp", conflicts_with = "serial", value_name = "SECONDS", default_value = Some("30"))]
+    /// The longest delay between reconnect attempts in seconds
+    #[arg(long, required = false, value_name = "SECONDS", default_value = Some("60"))]
+    reconnect_max_delay: u64,
+
+    /// If --tcp or --listen is specified, the idle time in seconds before keepalive probes are sent
+    #[arg(long, required = false, requires = "network", value_name = "SECONDS", default_value = Some("30"))]
     keepalive_idle: u64,
 
-    /// If --tcp is specified, the interval between individual keepalive probes in seconds
-    #[arg(long, required = false, requires = "tcp", conflicts_with = "serial", value_name = "SECONDS", default_value = Some("10"))]
+    /// If --tcp or --listen is specified, the interval between individual keepalive probes in seconds
+    #[arg(long, required = false, requires = "network", value_name = "SECONDS", default_value = Some("10"))]
     keepalive_interval: u64,
 
-    /// If --tcp is specified, the number of unacknowledged TCP probes before the connection is considered dead
-    #[arg(long, required = false, requires = "tcp", conflicts_with = "serial", value_name = "SECONDS", default_value = Some("5"))]
+    /// If --tcp or --listen is specified, the number of unacknowledged TCP probes before the connection is considered dead
+    #[arg(long, required = false, requires = "network", value_name = "SECONDS", default_value = Some("5"))]
     keepalive_count: u32,
+
+    /// Re-broadcast everything read from the source to any number of TCP clients connecting to this local address and port
+    #[arg(long, required = false, value_name = "ADDRESS:PORT")]
+    serve_raw: Option<String>,
+
+    /// If --serve-raw is specified, send the bytes as received (raw), or as timestamped capture records (capture)
+    #[arg(long, required = false, requires = "serve_raw", value_name = "FORMAT", default_value = Some("raw"))]
+    serve_format: serve::Format,
+
+    #[command(flatten)]
+    #[cfg(any(feature = "serialport", unix))]
+    line: SerialLine,
+}
+
+#[derive(Args, Debug, Clone)]
+#[group(skip)]
+#[cfg(any(feature = "serialport", unix))]
+struct SerialLine {
+    /// If --serial or --tty is specified, the baud rate
+    #[arg(long, required = false, requires = "serial_line", value_name = "BAUD", default_value = Some("38400"))]
+    baud_rate: u32,
+
+    /// If --serial or --tty is specified, the parity
+    #[arg(long, required = false, requires = "serial_line", value_name = "PARITY", default_value = Some("none"))]
+    parity: config::Parity,
+
+    /// If --serial or --tty is specified, the number of stop bits
+    #[arg(long, required = false, requires = "serial_line", value_name = "BITS", default_value = Some("1"))]
+    stop_bits: config::StopBits,
+
+    /// If --serial or --tty is specified, how long to wait for further bytes before passing on those received in milliseconds
+    #[arg(long, required = false, requires = "serial_line", value_name = "MILLISECONDS", default_value = Some("5"))]
+    read_timeout: u64,
+
+    /// If --serial or --tty is specified, ask the driver to pass on received bytes immediately (Linux only)
+    #[arg(long, required = false, requires = "serial_line")]
+    low_latency: bool,
+
+    /// If --serial or --tty is specified, put the UART into RS-485 mode with hardware driver-enable control (Linux only)
+    #[arg(long, required = false, requires = "serial_line")]
+    rs485: bool,
+
+    /// If --rs485 is specified, drive RTS low rather than high while sending
+    #[arg(long, required = false, requires = "rs485")]
+    rs485_rts_active_low: bool,
+
+    /// If --rs485 is specified, keep receiving while sending
+    #[arg(long, required = false, requires = "rs485")]
+    rs485_rx_during_tx: bool,
+
+    /// If --rs485 is specified, the delay between enabling the driver and sending in milliseconds
+    #[arg(long, required = false, requires = "rs485", value_name = "MILLISECONDS", default_value = Some("0"))]
+    rs485_delay_before_send: u32,
+
+    /// If --rs485 is specified, the delay between sending and disabling the driver in milliseconds
+    #[arg(long, required = false, requires = "rs485", value_name = "MILLISECONDS", default_value = Some("0"))]
+    rs485_delay_after_send: u32,
+}
+
+#[cfg(any(feature = "serialport", unix))]
+impl From<SerialLine> for config::SerialLineConfig {
+    fn from(value: SerialLine) -> Self {
+        Self {
+            baud_rate: value.baud_rate,
+            parity: value.parity,
+            stop_bits: value.stop_bits,
+            read_timeout: value.read_timeout,
+            low_latency: value.low_latency,
+            rs485: value.rs485.then_some(config::Rs485Config {
+                rts_active_low: value.rs485_rts_active_low,
+                rx_during_tx: value.rs485_rx_during_tx,
+                delay_rts_before_send: value.rs485_delay_before_send,
+                delay_rts_after_send: value.rs485_delay_after_send,
+            }),
+        }
+    }
 }
 
 impl Source {
-    fn read<F>(&self, mut callback: F)
+    /// Read from the source, passing each slice to `callback` along with the time it was received.
+    ///
+    /// Exits if the source can't be read.
+    fn read<F>(&self, callback: F)
+    where
+        F: FnMut(&[u8], SystemTime),
+    {
+        if let Err(e) = self.try_read(callback) {
+            log::error!("{}, exiting", e);
+            exit(source_error_status(&e));
+        }
+    }
+
+    /// Read from the source as `read()` does, returning an error once reconnect retries run out.
+    fn try_read<F>(&self, mut callback: F) -> Result<(), config::SourceError>
     where
-        F: FnMut(&[u8]),
+        F: FnMut(&[u8], SystemTime),
     {
-        let source = config::SourceConfig::from(self.clone());
-        let reconnect_timeout = Duration::from_secs(self.reconnect_timeout);
-        let reconnect_delay = Duration::from_secs(self.reconnect_delay);
-        let mut reconnect_retry = 0;
-
-        loop {
-            let mut buffer = [0u8; 1024];
-            let mut conn;
-
-            log::info!("opening source connection...");
-            match source.open() {
-                Ok(s) => {
-                    conn = s;
-                    log::info!("source opened, entering read loop");
+        let server = self.serve_raw.as_ref().map(|addr| {
+            match serve::Server::bind(addr.as_str(), self.serve_format, self.describe()) {
+                Ok(server) => {
+                    log::info!("serving raw data on {}", server.local_addr());
+                    server
                 }
                 Err(e) => {
-                    log::error!("error opening source: {}", e);
-                    reconnect_retry += 1;
-                    if self.reconnect_retry != 0 && reconnect_retry > self.reconnect_retry {
-                        log::warn!(
-                            "maximum reconnect retries ({}) exceeded, exiting",
-                            self.reconnect_retry.to_string()
-                        );
-                        exit(2);
-                    } else {
-                        log::info!(
-                            "reconnect retry {}/{}",
-                            reconnect_retry,
-                            if self.reconnect_retry == 0 {
-                                "∞".to_string()
-                            } else {
-                                self.reconnect_retry.to_string()
-                            }
-                        );
-                        log::info!("reconnecting in {:?}...", reconnect_delay);
-                        sleep(reconnect_delay);
-                        continue;
-                    }
+                    log::error!("error serving raw data on {}: {}", addr, e);
+                    exit(1);
                 }
-            };
+            }
+        });
+        let callback = move |slice: &[u8], timestamp: SystemTime| {
+            assert_read_only();
+            if let Some(server) = &server {
+                server.broadcast(slice, timestamp);
+            }
+            callback(slice, timestamp)
+        };
 
-            let mut last_received = Instant::now();
-
-            loop {
-                let slice;
-                match conn.read(&mut buffer) {
-                    Ok(n) => {
-                        if n == 0 {
-                            log::warn!("connection closed by peer, will reconnect");
-                            break; // outer loop will reopen
-                        }
-                        last_received = Instant::now();
-                        reconnect_retry = 0;
-                        slice = &buffer[..n];
-                    }
-                    Err(e) => match e.kind() {
-                        ErrorKind::TimedOut | ErrorKind::WouldBlock => {
-                            if self.reconnect_timeout == 0
-                                || last_received.elapsed() < reconnect_timeout
-                            {
-                                // temporary, continue reading
-                                continue;
-                            } else {
-                                log::warn!(
-                                    "no data for {:?}, reconnecting (idle timeout)",
-                                    reconnect_timeout
-                                );
-                                reconnect_retry += 1;
-                                if self.reconnect_retry != 0
-                                    && reconnect_retry > self.reconnect_retry
-                                {
-                                    log::warn!(
-                                        "maximum reconnect retries ({}) exceeded, exiting",
-                                        self.reconnect_retry
-                                    );
-                                    exit(3);
-                                } else {
-                                    log::info!(
-                                        "reconnect retry {}/{}",
-                                        reconnect_retry,
-                                        if self.reconnect_retry == 0 {
-                                            "∞".to_string()
-                                        } else {
-                                            self.reconnect_retry.to_string()
-                                        }
-                                    );
-                                }
-                                break;
-                            }
-                        }
-                        ErrorKind::Interrupted => continue,
-                        _ => {
-                            log::error!("error reading: {}, will reconnect", e);
-                            break;
-                        }
-                    },
-                };
-                callback(slice);
+        let result = if let Some(path) = &self.capture {
+            self.replay(path, callback);
+            Ok(())
+        } else {
+            let source = config::SourceConfig::from(self.clone());
+            config::SourceReader::new(source, self.reconnect_config()).run(callback)
+        };
+
+        assert_read_only();
+        result
+    }
+
+    /// Describe the source, e.g. for the metadata of capture files.
+    fn describe(&self) -> String {
+        match &self.capture {
+            Some(path) => format!("capture {}", path.display()),
+            None => config::SourceConfig::from(self.clone()).to_string(),
+        }
+    }
+
+    /// Read from `source`, reconnecting according to the options given on the command line.
+    fn read_config<F>(&self, source: &config::SourceConfig, mut callback: F)
+    where
+        F: FnMut(&[u8], SystemTime),
+    {
+        let mut reader = config::SourceReader::new(source.clone(), self.reconnect_config());
+        let result = reader.run(|slice, timestamp| {
+            assert_read_only();
+            callback(slice, timestamp)
+        });
+        assert_read_only();
+        if let Err(e) = result {
+            log::error!("{}, exiting", e);
+            exit(source_error_status(&e));
+        }
+    }
+
+    fn reconnect_config(&self) -> config::ReconnectConfig {
+        config::ReconnectConfig {
+            idle_timeout: (self.reconnect_timeout != 0)
+                .then(|| Duration::from_secs(self.reconnect_timeout)),
+            frame_timeout: (self.frame_timeout != 0)
+                .then(|| Duration::from_secs(self.frame_timeout)),
+            delay: Duration::from_secs(self.reconnect_delay),
+            max_delay: Duration::from_secs(self.reconnect_max_delay.max(self.reconnect_delay)),
+            max_retries: (self.reconnect_retry != 0).then_some(self.reconnect_retry),
+            ..config::ReconnectConfig::default()
+        }
+    }
+}
+
+/// Panic if anything has been written to a connection, since the CLI only ever listens.
+///
+/// This is checked as each slice is read, so that a write is caught as soon as the reader sees
+/// more data rather than only once the source ends.
+fn assert_read_only() {
+    assert_eq!(
+        physical::bytes_written(),
+        0,
+        "bytes were written to the bus"
+    );
+}
+
+/// The exit status for a source which couldn't be read: 2 if it couldn't be opened, 3 otherwise.
+fn source_error_status(error: &config::SourceError) -> i32 {
+    match error {
+        config::SourceError::Open { .. } => 2,
+        _ => 3,
+    }
+}
+
+impl Source {
+    fn replay<F>(&self, path: &Path, mut callback: F)
+    where
+        F: FnMut(&[u8], SystemTime),
+    {
+        let replay = File::open(path)
+            .and_then(capture::Reader::new)
+            .map(|reader| capture::Replay::new(reader, self.replay_speed));
+        let replay = match replay {
+            Ok(replay) => replay,
+            Err(e) => {
+                log::error!("error opening capture {}: {}", path.display(), e);
+                exit(1);
+            }
+        };
+
+        log::info!("replaying capture {}", path.display());
+        for record in replay {
+            match record {
+                Ok(record) => callback(&record.data, record.timestamp),
+                Err(e) => {
+                    log::error!("error reading capture {}: {}", path.display(), e);
+                    exit(1);
+                }
             }
-            log::info!("reconnecting in {:?}...", reconnect_delay);
-            sleep(reconnect_delay);
         }
+        log::info!("end of capture {}", path.display());
     }
 }
 
@@ -223,7 +532,48 @@ impl From<Source> for config::SourceConfig {
     fn from(value: Source) -> Self {
         #[cfg(feature = "serialport")]
         if let Some(name) = value.serial {
-            return config::SerialSourceConfig { name }.into();
+            return config::SerialSourceConfig {
+                name,
+                line: value.line.into(),
+            }
+            .into();
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = value.tty {
+            return config::TermiosSourceConfig {
+                path,
+                line: value.line.into(),
+            }
+            .into();
+        }
+
+        #[cfg(all(feature = "trace_meshdcd", target_os = "linux"))]
+        if let Some(process_name) = value.trace_meshdcd {
+            return config::SourceConfig::TraceMeshdcd { process_name };
+        }
+
+        if value.stdin || value.file.is_some() {
+            let input = match value.file {
+                Some(path) => config::StreamInput::File(path),
+                None => config::StreamInput::Stdin,
+            };
+            return config::StreamSourceConfig {
+                input,
+                follow: value.follow,
+            }
+            .into();
+        }
+
+        if let Some(address) = value.listen {
+            return config::TcpListenConfig {
+                address,
+                mode: config::ConnectionMode::ReadOnly,
+                keepalive_idle: value.keepalive_idle,
+                keepalive_interval: value.keepalive_interval,
+                keepalive_count: value.keepalive_count,
+            }
+            .into();
         }
 
         match (value.tcp,) {
@@ -256,8 +606,11 @@ fn main() {
             peek_bytes(source, raw);
         }
 
-        Commands::PeekFrames { source } => {
-            peek_frames(source);
+        Commands::PeekFrames {
+            source,
+            show_errors,
+        } => {
+            peek_frames(source, show_errors);
         }
 
         Commands::PeekActivity { source } => {
@@ -267,9 +620,54 @@ fn main() {
         Commands::Observe {
             source,
             persistent_file,
-        } => observe(source, persistent_file),
+            sources,
+        } => match sources {
+            Some(path) => observe_sources(source, path),
+            None => observe(source, persistent_file),
+        },
 
-        #[cfg(feature = "serialport")]
+        Commands::Record {
+            source,
+            output,
+            rotate_size,
+            rotate_interval,
+            flush_interval,
+        } => record(source, output, rotate_size, rotate_interval, flush_interval),
+
+        Commands::ExportPcap {
+            source,
+            output,
+            raw_bytes,
+        } => export_pcap(source, output, raw_bytes),
+
+        Commands::Capture(command) => capture_command(command),
+
+        Commands::Detect {
+            tcp,
+            no_serial,
+            duration,
+        } => detect(tcp, no_serial, Duration::from_secs(duration)),
+
+        Commands::Decode { hex, file } => decode(hex, file),
+
+        #[cfg(feature = "transmit")]
+        Commands::Poll {
+            source,
+            gateways,
+            poll_interval,
+            no_echo,
+            read_write,
+            persistent_file,
+        } => poll(
+            source,
+            gateways,
+            Duration::from_millis(poll_interval),
+            no_echo,
+            read_write,
+            persistent_file,
+        ),
+
+        #[cfg(any(feature = "serialport", unix))]
         Commands::ListSerialPorts => {
             list_serial_ports();
         }
@@ -277,7 +675,7 @@ fn main() {
 }
 
 fn peek_bytes(source: Source, raw: bool) {
-    source.read(|slice| {
+    source.read(|slice, _| {
         let mut out = std::io::stdout().lock();
         if raw {
             out.write_all(slice).unwrap();
@@ -299,15 +697,23 @@ fn peek_bytes(source: Source, raw: bool) {
     });
 }
 
-fn peek_frames(source: Source) {
-    struct Sink;
+fn peek_frames(source: Source, show_errors: bool) {
+    struct Sink {
+        show_errors: bool,
+    }
     impl taptap::gateway::link::Sink for Sink {
-        fn frame(&mut self, frame: Frame) {
-            println!("{:?}", frame);
+        fn frame(&mut self, frame: FrameRef<'_>) {
+            println!("{:?}", Frame::from(frame));
+        }
+
+        fn error(&mut self, kind: gateway::link::ErrorKind, raw: &[u8]) {
+            if self.show_errors {
+                println!("Error {{ kind: {:?}, raw: {:02X?} }}", kind, raw);
+            }
         }
     }
-    let mut rx = taptap::gateway::link::Receiver::new(Sink);
-    source.read(|slice| rx.extend_from_slice(slice));
+    let mut rx = taptap::gateway::link::Receiver::new(Sink { show_errors });
+    source.read(|slice, timestamp| rx.extend_from_slice_at(slice, timestamp));
 }
 
 fn peek_activity(source: Source) {
@@ -316,11 +722,16 @@ fn peek_activity(source: Source) {
         slot_counters: BTreeMap<GatewayID, SlotCounter>,
     }
     impl gateway::transport::Sink for Sink {
-        fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID) {
+        fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID, _: SystemTime) {
             log::info!("enumeration started (at {:?})", enumeration_gateway_id);
         }
 
-        fn gateway_identity_observed(&mut self, gateway_id: GatewayID, address: LongAddress) {
+        fn gateway_identity_observed(
+            &mut self,
+            gateway_id: GatewayID,
+            address: LongAddress,
+            _: SystemTime,
+        ) {
             log::info!(
                 "gateway identity observed: {:?} = {:?}",
                 gateway_id,
@@ -328,20 +739,26 @@ fn peek_activity(source: Source) {
             );
         }
 
-        fn gateway_version_observed(&mut self, gateway_id: GatewayID, version: &str) {
+        fn gateway_version_observed(
+            &mut self,
+            gateway_id: GatewayID,
+            version: &str,
+            _: SystemTime,
+        ) {
             log::info!("gateway version observed: {:?} = {:?}", gateway_id, version);
         }
 
-        fn enumeration_ended(&mut self, gateway_id: GatewayID) {
+        fn enumeration_ended(&mut self, gateway_id: GatewayID, _: SystemTime) {
             log::info!("enumeration ended: {:?}", gateway_id);
         }
 
-        fn gateway_slot_counter_captured(&mut self, _gateway_id: GatewayID) {}
+        fn gateway_slot_counter_captured(&mut self, _: GatewayID, _: SystemTime) {}
 
         fn gateway_slot_counter_observed(
             &mut self,
             gateway_id: GatewayID,
             slot_counter: SlotCounter,
+            _: SystemTime,
         ) {
             let print = match self.slot_counters.entry(gateway_id) {
                 Entry::Vacant(e) => {
@@ -367,6 +784,7 @@ fn peek_activity(source: Source) {
             gateway_id: GatewayID,
             header: &ReceivedPacketHeader,
             data: &[u8],
+            _: SystemTime,
         ) {
             match header.packet_type {
                 PacketType::STRING_RESPONSE
@@ -377,11 +795,25 @@ fn peek_activity(source: Source) {
             log::info!("packet received: {:?} {:?} {:?}", gateway_id, header, data);
         }
 
+        fn receive_response_retransmitted(
+            &mut self,
+            gateway_id: GatewayID,
+            packets: ReceivedPackets<'_>,
+            _: SystemTime,
+        ) {
+            log::info!(
+                "receive response retransmitted: {:?} repeating {} packets",
+                gateway_id,
+                packets.count()
+            );
+        }
+
         fn command_executed(
             &mut self,
             gateway_id: GatewayID,
             request: (PacketType, &[u8]),
             response: (PacketType, &[u8]),
+            _: SystemTime,
         ) {
             match request.0 {
                 PacketType::STRING_REQUEST => return,
@@ -400,7 +832,13 @@ fn peek_activity(source: Source) {
         }
     }
     impl pv::application::Sink for Sink {
-        fn string_request(&mut self, gateway_id: GatewayID, pv_node_id: NodeID, request: &str) {
+        fn string_request(
+            &mut self,
+            gateway_id: GatewayID,
+            pv_node_id: NodeID,
+            request: &str,
+            _: SystemTime,
+        ) {
             log::info!(
                 "string request: {:?} {:?} {:?}",
                 gateway_id,
@@ -409,7 +847,13 @@ fn peek_activity(source: Source) {
             );
         }
 
-        fn string_response(&mut self, gateway_id: GatewayID, pv_node_id: NodeID, response: &str) {
+        fn string_response(
+            &mut self,
+            gateway_id: GatewayID,
+            pv_node_id: NodeID,
+            response: &str,
+            _: SystemTime,
+        ) {
             log::info!(
                 "string response: {:?} {:?} {:?}",
                 gateway_id,
@@ -423,6 +867,7 @@ fn peek_activity(source: Source) {
             gateway_id: GatewayID,
             start_address: NodeAddress,
             nodes: &[NodeTableResponseEntry],
+            _: SystemTime,
         ) {
             log::info!(
                 "node table page: {:?} start {:?} {:?}",
@@ -437,6 +882,7 @@ fn peek_activity(source: Source) {
             gateway_id: GatewayID,
             pv_node_id: NodeID,
             topology_report: &TopologyReport,
+            _: SystemTime,
         ) {
             log::info!(
                 "topology report: {:?} {:?} {:?}",
@@ -451,6 +897,7 @@ fn peek_activity(source: Source) {
             gateway_id: GatewayID,
             pv_node_id: NodeID,
             power_report: &PowerReport,
+            _: SystemTime,
         ) {
             log::info!(
                 "power report: {:?} {:?} {:?}",
@@ -465,29 +912,745 @@ fn peek_activity(source: Source) {
         pv::application::Receiver::new(Sink::default()),
     ));
 
-    source.read(|slice| rx.extend_from_slice(slice));
+    source.read(|slice, timestamp| rx.extend_from_slice_at(slice, timestamp));
 }
 
 fn observe(source: Source, persistent_file: String) {
+    // Timestamp observations with the time each slice was received, which for a replayed capture
+    // is the time it was originally recorded
     let observer = taptap::observer::Observer::new(persistent_file);
     let mut rx = gateway::link::Receiver::new(gateway::transport::Receiver::new(
         pv::application::Receiver::new(observer),
     ));
-    source.read(|slice| rx.extend_from_slice(slice));
+    source.read(|slice, timestamp| rx.extend_from_slice_at(slice, timestamp));
 }
 
-#[cfg(feature = "serialport")]
-fn list_serial_ports() {
-    use serialport::SerialPortType;
+/// Observe each of the sources listed in the file at `path` on its own thread, labelling events
+/// with the name of their source.
+fn observe_sources(source: Source, path: PathBuf) {
+    let config = std::fs::read_to_string(&path).and_then(|string| {
+        serde_json::from_str::<config::ObserveConfig>(&string)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+    });
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("error reading sources from {}: {}", path.display(), e);
+            exit(1);
+        }
+    };
+    if let Err(e) = config.validate() {
+        log::error!("invalid sources in {}: {}", path.display(), e);
+        exit(1);
+    }
+
+    let threads = config
+        .sources
+        .into_iter()
+        .map(|observed| {
+            let source = source.clone();
+            std::thread::Builder::new()
+                .name(observed.name.clone())
+                .spawn(move || {
+                    log::info!("observing source {}", observed.name);
+                    let observer = taptap::observer::Observer::with_source(
+                        observed.name,
+                        observed.persistent_file,
+                        taptap::observer::FrameClock::default(),
+                    );
+                    let mut rx = gateway::link::Receiver::new(gateway::transport::Receiver::new(
+                        pv::application::Receiver::new(observer),
+                    ));
+                    source.read_config(&observed.source, |slice, timestamp| {
+                        rx.extend_from_slice_at(slice, timestamp)
+                    });
+                })
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().ok();
+    }
+}
 
-    let mut ports = match physical::serialport::PortInfo::list() {
-        Ok(ports) => ports,
+/// Create the file at `path`, or use standard output if `path` is `-`.
+fn create_output(path: &Path) -> BufWriter<Box<dyn Write>> {
+    let file: Box<dyn Write> = if path.as_os_str() == "-" {
+        Box::new(std::io::stdout().lock())
+    } else {
+        match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                log::error!("error creating {}: {}", path.display(), e);
+                exit(1);
+            }
+        }
+    };
+    BufWriter::new(file)
+}
+
+fn export_pcap(source: Source, output: PathBuf, raw_bytes: bool) {
+    let file = create_output(&output);
+    let mut writer = match taptap::pcap::Writer::new(file, source.describe()) {
+        Ok(writer) => writer,
         Err(e) => {
-            log::error!("error listing serial ports: {}", e);
+            log::error!("error writing {}: {}", output.display(), e);
             exit(1);
         }
     };
 
+    // Write live traffic as it arrives, e.g. for `wireshark -k -i -`
+    let live = source.capture.is_none();
+
+    let mut rx = gateway::link::Receiver::new(Vec::new());
+    source.read(|slice, timestamp| {
+        rx.extend_from_slice(slice);
+        let result = (|| -> std::io::Result<()> {
+            if raw_bytes {
+                writer.write_bytes(slice, timestamp, None)?;
+            }
+            for frame in rx.sink_mut().drain(..) {
+                writer.write_frame(&frame, timestamp)?;
+            }
+            if live {
+                writer.flush()?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            log::error!("error writing {}: {}", output.display(), e);
+            exit(1);
+        }
+    });
+
+    if let Err(e) = writer.flush() {
+        log::error!("error writing {}: {}", output.display(), e);
+        exit(1);
+    }
+}
+
+fn decode(hex: Vec<String>, file: Option<PathBuf>) {
+    let text = if let Some(path) = file {
+        std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            log::error!("error reading {}: {}", path.display(), e);
+            exit(1);
+        })
+    } else if hex.is_empty() {
+        std::io::read_to_string(std::io::stdin()).unwrap_or_else(|e| {
+            log::error!("error reading standard input: {}", e);
+            exit(1);
+        })
+    } else {
+        hex.join(" ")
+    };
+
+    let bytes = match decode::parse_hex(&text) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::error!("{}", e);
+            exit(1);
+        }
+    };
+    print!("{}", decode::decode(&bytes));
+}
+
+#[cfg(feature = "transmit")]
+fn parse_gateway_id(value: &str) -> Result<GatewayID, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    let id = u16::from_str_radix(digits, 16).map_err(|e| e.to_string())?;
+    GatewayID::try_from(id).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "transmit")]
+fn poll(
+    source: Source,
+    gateways: Vec<GatewayID>,
+    interval: Duration,
+    no_echo: bool,
+    read_write: bool,
+    persistent_file: String,
+) {
+    // Polling is writing, which TCP sources only permit when asked to
+    let mut config = config::SourceConfig::from(source);
+    if read_write {
+        match &mut config {
+            config::SourceConfig::Tcp(tcp) => tcp.mode = config::ConnectionMode::ReadWrite,
+            config::SourceConfig::TcpListen(listen) => {
+                listen.mode = config::ConnectionMode::ReadWrite
+            }
+            _ => {}
+        }
+    }
+    let conn = config.open_read_write().unwrap_or_else(|e| {
+        if e.kind() == ErrorKind::PermissionDenied {
+            log::error!(
+                "error opening {}: {} (pass --read-write to allow polling)",
+                config,
+                e
+            );
+        } else {
+            log::error!("error opening {}: {}", config, e);
+        }
+        exit(2);
+    });
+
+    let observer = taptap::observer::Observer::new(persistent_file);
+    let sink = gateway::transport::Receiver::new(pv::application::Receiver::new(observer));
+    let mut poll_config = gateway::transport::PollConfig {
+        gateways,
+        interval,
+        ..Default::default()
+    };
+    if no_echo {
+        poll_config.transmit.echo_timeout = None;
+    }
+    let mut poller = gateway::transport::Poller::new(conn, poll_config, sink).unwrap_or_else(|e| {
+        log::error!("error polling {}: {}", config, e);
+        exit(2);
+    });
+
+    install_shutdown_handler();
+    if let Err(e) = poller.run(|| !SHUTDOWN.load(Ordering::SeqCst)) {
+        log::error!("error polling {}: {}, exiting", config, e);
+        exit(3);
+    }
+}
+
+fn detect(tcp: Vec<String>, no_serial: bool, duration: Duration) {
+    let mut sources = if no_serial {
+        Vec::new()
+    } else {
+        serial_sources()
+    };
+    for endpoint in tcp {
+        let (hostname, port) = match endpoint.rsplit_once(':') {
+            Some((hostname, port)) if !hostname.contains(':') => match port.parse() {
+                Ok(port) => (hostname.to_owned(), port),
+                Err(e) => {
+                    log::error!("invalid port in {}: {}", endpoint, e);
+                    exit(1);
+                }
+            },
+            _ => (endpoint, 502),
+        };
+        sources.push(
+            config::TcpConnectionConfig {
+                hostname,
+                port,
+                mode: config::ConnectionMode::ReadOnly,
+                keepalive_idle: 30,
+                keepalive_interval: 10,
+                keepalive_count: 5,
+            }
+            .into(),
+        );
+    }
+
+    if sources.is_empty() {
+        println!("Nothing to probe.");
+        return;
+    }
+
+    log::info!("listening to {} sources for {:?}", sources.len(), duration);
+    let probes = taptap::detect::probe_all(sources, duration);
+
+    println!("Ranked by valid frames:");
+    for probe in &probes {
+        println!("    {}", source_arguments(&probe.source));
+        let verdict = probe.verdict();
+        match (&probe.error, verdict) {
+            (Some(e), taptap::detect::Verdict::Failed) => println!("      {}: {}", verdict, e),
+            (_, taptap::detect::Verdict::Silent) => println!("      {}", verdict),
+            _ => println!(
+                "      {}: {} frames, {} checksum errors, {} noise in {} bytes",
+                verdict,
+                probe.counters.frames,
+                probe.counters.checksums,
+                probe.counters.noise,
+                probe.bytes
+            ),
+        }
+    }
+}
+
+/// The serial ports on this system, as sources with the default line settings.
+fn serial_sources() -> Vec<config::SourceConfig> {
+    #[cfg(feature = "serialport")]
+    match physical::serialport::PortInfo::list() {
+        Ok(ports) => {
+            return ports
+                .into_iter()
+                .map(|port| {
+                    config::SerialSourceConfig {
+                        name: port.name().to_owned(),
+                        line: Default::default(),
+                    }
+                    .into()
+                })
+                .collect()
+        }
+        Err(e) => log::warn!("error listing serial ports: {}", e),
+    }
+
+    #[cfg(unix)]
+    match physical::termios::available_ports() {
+        Ok(ports) => {
+            return ports
+                .into_iter()
+                .map(|path| {
+                    config::TermiosSourceConfig {
+                        path,
+                        line: Default::default(),
+                    }
+                    .into()
+                })
+                .collect()
+        }
+        Err(e) => log::warn!("error listing serial ports: {}", e),
+    }
+
+    #[allow(unreachable_code)]
+    Vec::new()
+}
+
+/// The command line arguments selecting `source`.
+fn source_arguments(source: &config::SourceConfig) -> String {
+    match source {
+        #[cfg(feature = "serialport")]
+        config::SourceConfig::Serial(config) => format!("--serial {}", config.name),
+        #[cfg(unix)]
+        config::SourceConfig::Termios(config) => format!("--tty {}", config.path.display()),
+        config::SourceConfig::Tcp(config) => {
+            format!("--tcp {} --port {}", config.hostname, config.port)
+        }
+        other => other.to_string(),
+    }
+}
+
+fn open_capture(path: &Path) -> capture::Reader<File> {
+    match File::open(path).and_then(capture::Reader::new) {
+        Ok(reader) => reader,
+        Err(e) => {
+            log::error!("error opening capture {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
+
+/// The metadata of the capture at `path`, or a description of it for version 1 captures.
+fn capture_metadata<R: std::io::Read>(
+    reader: &capture::Reader<R>,
+    path: &Path,
+) -> capture::Metadata {
+    reader
+        .metadata()
+        .cloned()
+        .unwrap_or_else(|| capture::Metadata::new(format!("capture {}", path.display())))
+}
+
+fn capture_command(command: CaptureCommands) {
+    match command {
+        CaptureCommands::Info { inputs } => {
+            for path in inputs {
+                capture_info(&path);
+            }
+        }
+
+        CaptureCommands::Slice {
+            input,
+            output,
+            from,
+            to,
+        } => {
+            let reader = open_capture(&input);
+            let metadata = capture_metadata(&reader, &input);
+            let from = from.map(SystemTime::from);
+            let to = to.map(SystemTime::from);
+            let records = reader.filter(|record| match record {
+                Ok(record) => {
+                    from.is_none_or(|from| record.timestamp >= from)
+                        && to.is_none_or(|to| record.timestamp < to)
+                }
+                Err(_) => true,
+            });
+            write_capture(&output, &metadata, records);
+        }
+
+        CaptureCommands::Merge { inputs, output } => {
+            let readers = inputs
+                .iter()
+                .map(|path| open_capture(path))
+                .collect::<Vec<_>>();
+            let metadata = inputs
+                .iter()
+                .zip(&readers)
+                .map(|(path, reader)| capture_metadata(reader, path))
+                .collect::<Vec<_>>();
+            let metadata = capture::Metadata {
+                source: metadata
+                    .iter()
+                    .map(|metadata| metadata.source.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                start_time: metadata
+                    .iter()
+                    .map(|metadata| metadata.start_time)
+                    .min()
+                    .unwrap(), // inputs are required
+                ..capture::Metadata::new("")
+            };
+            write_capture(&output, &metadata, capture::Merge::new(readers));
+        }
+
+        CaptureCommands::Cat { inputs, output } => {
+            let first = open_capture(&inputs[0]); // inputs are required
+            let metadata = capture_metadata(&first, &inputs[0]);
+            drop(first);
+            let records = inputs.iter().flat_map(|path| open_capture(path));
+            write_capture(&output, &metadata, records);
+        }
+    }
+}
+
+fn capture_info(path: &Path) {
+    let reader = open_capture(path);
+    let version = reader.version();
+    let metadata = reader.metadata().cloned();
+    let summary = match capture::Summary::of(reader) {
+        Ok(summary) => summary,
+        Err(e) => {
+            log::error!("error reading capture {}: {}", path.display(), e);
+            exit(1);
+        }
+    };
+
+    let time = |t: Option<SystemTime>| {
+        t.map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339())
+            .unwrap_or_else(|| "-".into())
+    };
+    println!("{}:", path.display());
+    println!("  version:         {}", version);
+    if let Some(metadata) = metadata {
+        println!("  source:          {}", metadata.source);
+        println!("  host:            {}", metadata.host);
+        println!("  taptap version:  {}", metadata.taptap_version);
+        println!("  start time:      {}", metadata.start_time.to_rfc3339());
+    }
+    println!("  records:         {}", summary.records);
+    println!("  bytes:           {}", summary.bytes);
+    println!("  first record:    {}", time(summary.first));
+    println!("  last record:     {}", time(summary.last));
+    println!("  duration:        {:?}", summary.duration());
+    println!("  frames:          {}", summary.counters.frames);
+    println!("  runts:           {}", summary.counters.runts);
+    println!("  giants:          {}", summary.counters.giants);
+    println!("  checksum errors: {}", summary.counters.checksums);
+    println!("  noise:           {}", summary.counters.noise);
+}
+
+/// Write `records` to a new capture at `path`.
+fn write_capture<I>(path: &Path, metadata: &capture::Metadata, records: I)
+where
+    I: Iterator<Item = std::io::Result<capture::Record>>,
+{
+    let result = (|| -> std::io::Result<()> {
+        let mut writer = capture::Writer::new(create_output(path), metadata)?;
+        for record in records {
+            writer.write_record(&record?)?;
+        }
+        writer.finish()?.flush()
+    })();
+
+    if let Err(e) = result {
+        log::error!("error copying records to {}: {}", path.display(), e);
+        exit(1);
+    }
+}
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_shutdown(_signal: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+/// Set `SHUTDOWN` on SIGINT and SIGTERM instead of terminating the process.
+fn install_shutdown_handler() {
+    let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+fn record(
+    source: Source,
+    output: PathBuf,
+    rotate_size: u64,
+    rotate_interval: u64,
+    flush_interval: u64,
+) {
+    install_shutdown_handler();
+
+    let description = source.describe();
+
+    // Read the source on its own thread, so that flushing, rotation and shutdown don't depend on
+    // data arriving. Errors reading the source are sent back too, so that the capture file is
+    // finished before exiting.
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let result = source.try_read(|slice, timestamp| {
+            tx.send(Ok((slice.to_vec(), timestamp))).ok();
+        });
+        if let Err(e) = result {
+            tx.send(Err(e)).ok();
+        }
+    });
+
+    let mut recorder = Recorder::new(
+        output,
+        description,
+        rotate_size,
+        Duration::from_secs(rotate_interval),
+    );
+    let flush_interval = Duration::from_secs(flush_interval);
+    let mut last_flush = Instant::now();
+
+    let mut source_error = None;
+    let result = (|| -> std::io::Result<()> {
+        while !SHUTDOWN.load(Ordering::SeqCst) {
+            match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(Ok((bytes, timestamp))) => recorder.write(&bytes, timestamp)?,
+                Ok(Err(e)) => {
+                    source_error = Some(e);
+                    break;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            if !flush_interval.is_zero() && last_flush.elapsed() >= flush_interval {
+                recorder.flush()?;
+                last_flush = Instant::now();
+            }
+
+            recorder.rotate_if_due()?;
+        }
+
+        log::info!("shutting down, closing capture file");
+        recorder.finish()
+    })();
+
+    if let Err(e) = result {
+        log::error!("error writing capture file: {}", e);
+        exit(1);
+    }
+    if let Some(e) = source_error {
+        log::error!("{}, exiting", e);
+        exit(source_error_status(&e));
+    }
+}
+
+/// A `Write` which counts the bytes written through it.
+#[derive(Debug)]
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+type RecorderWriter = capture::Writer<CountingWriter<BufWriter<File>>>;
+
+/// Writes slices to capture files, starting a new file by size or age if requested.
+#[derive(Debug)]
+struct Recorder {
+    output: PathBuf,
+    source: String,
+    rotate_size: u64,
+    rotate_interval: Duration,
+    current: Option<(RecorderWriter, Instant)>,
+}
+
+impl Recorder {
+    fn new(output: PathBuf, source: String, rotate_size: u64, rotate_interval: Duration) -> Self {
+        Self {
+            output,
+            source,
+            rotate_size,
+            rotate_interval,
+            current: None,
+        }
+    }
+
+    fn rotating(&self) -> bool {
+        self.rotate_size != 0 || !self.rotate_interval.is_zero()
+    }
+
+    /// Open a capture file starting with a record received at `first_record`.
+    fn open(&self, first_record: SystemTime) -> std::io::Result<RecorderWriter> {
+        let file = if self.rotating() {
+            create_rotated_file(&self.output)?
+        } else {
+            log::info!("recording to {}", self.output.display());
+            File::create(&self.output)?
+        };
+
+        // The file is only opened once its first record has been read, so the capture started no
+        // later than that record
+        let mut metadata = capture::Metadata::new(self.source.as_str());
+        metadata.start_time = metadata.start_time.min(first_record.into());
+
+        capture::Writer::new(
+            CountingWriter {
+                inner: BufWriter::new(file),
+                count: 0,
+            },
+            &metadata,
+        )
+    }
+
+    fn write(&mut self, bytes: &[u8], timestamp: SystemTime) -> std::io::Result<()> {
+        let writer = match &mut self.current {
+            Some((writer, _)) => writer,
+            None => {
+                &mut self
+                    .current
+                    .insert((self.open(timestamp)?, Instant::now()))
+                    .0
+            }
+        };
+        writer.write(bytes, timestamp)?;
+        self.rotate_if_due()
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let Some((writer, _)) = &mut self.current {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Close the current file if it has grown too large or too old.
+    ///
+    /// The next file is opened when more data arrives.
+    fn rotate_if_due(&mut self) -> std::io::Result<()> {
+        let due = match &self.current {
+            Some((writer, opened)) => {
+                (self.rotate_size != 0 && writer.get_ref().count >= self.rotate_size)
+                    || (!self.rotate_interval.is_zero() && opened.elapsed() >= self.rotate_interval)
+            }
+            None => false,
+        };
+
+        if due {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if let Some((writer, _)) = self.current.take() {
+            writer.finish()?.inner.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Create a new capture file named after `output` and the current local time.
+///
+/// `site.taptap.gz` becomes `site-20240824T091641.taptap.gz`.
+fn create_rotated_file(output: &Path) -> std::io::Result<File> {
+    create_rotated_file_at(output, chrono::Local::now()).map(|(file, _)| file)
+}
+
+/// Create a new capture file named after `output` and `time`, returning it with its path.
+///
+/// If the name is taken, `-1`, `-2` etc. are added to the timestamp.
+fn create_rotated_file_at(
+    output: &Path,
+    time: chrono::DateTime<chrono::Local>,
+) -> std::io::Result<(File, PathBuf)> {
+    let file_name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (stem, extension) = match file_name.split_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (file_name.as_str(), String::new()),
+    };
+    let timestamp = time.format("%Y%m%dT%H%M%S");
+
+    let mut suffix = String::new();
+    for n in 1.. {
+        let path = output.with_file_name(format!("{}-{}{}{}", stem, timestamp, suffix, extension));
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(file) => {
+                log::info!("recording to {}", path.display());
+                return Ok((file, path));
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                suffix = format!("-{}", n);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(any(feature = "serialport", unix))]
+fn list_serial_ports() {
+    #[cfg(feature = "serialport")]
+    match physical::serialport::PortInfo::list() {
+        Ok(ports) => return print_serial_ports(ports),
+        #[cfg(unix)]
+        Err(e) => log::warn!("error listing serial ports: {}, scanning /dev instead", e),
+        #[cfg(not(unix))]
+        Err(e) => {
+            log::error!("error listing serial ports: {}", e);
+            exit(1);
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        let ports = match physical::termios::available_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                log::error!("error listing serial ports: {}", e);
+                exit(1);
+            }
+        };
+
+        if ports.is_empty() {
+            println!("No serial ports detected.")
+        } else {
+            println!("Detected:");
+        }
+
+        for port in ports {
+            println!("    --tty {}", port.display());
+        }
+    }
+}
+
+#[cfg(feature = "serialport")]
+fn print_serial_ports(mut ports: Vec<physical::serialport::PortInfo>) {
+    use serialport::SerialPortType;
+
     ports.sort_by_cached_key(|port| port.name().to_owned());
 
     if ports.is_empty() {
@@ -518,3 +1681,171 @@ fn list_serial_ports() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// A fresh directory for the files of one test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("taptap-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The names of the files in `dir`, sorted.
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// The data recorded in each of the capture files in `dir`, in the order they were created.
+    fn recorded(dir: &Path) -> Vec<Vec<u8>> {
+        let mut names = file_names(dir);
+        // `-1` sorts before `.`, so order by timestamp and then suffix
+        names.sort_by_key(|name| {
+            let (timestamp, rest) = name.split_at(name.len().min(20));
+            let n: u32 = rest
+                .strip_prefix('-')
+                .and_then(|rest| rest.split('.').next())
+                .map_or(0, |n| n.parse().unwrap());
+            (timestamp.to_string(), n)
+        });
+        names
+            .iter()
+            .map(|name| {
+                let reader = capture::Reader::new(File::open(dir.join(name)).unwrap()).unwrap();
+                assert_eq!(reader.metadata().unwrap().source, "test");
+                reader.flat_map(|record| record.unwrap().data).collect()
+            })
+            .collect()
+    }
+
+    /// Whether `name` is `site-YYYYMMDDTHHMMSS[-n].taptap.gz`.
+    fn is_rotated_name(name: &str) -> bool {
+        let Some(rest) = name
+            .strip_prefix("site-")
+            .and_then(|rest| rest.strip_suffix(".taptap.gz"))
+        else {
+            return false;
+        };
+        let (timestamp, n) = rest.split_at(rest.len().min(15));
+        chrono::NaiveDateTime::parse_from_str(timestamp, "%Y%m%dT%H%M%S").is_ok()
+            && (n.is_empty() || n[1..].parse::<u32>().is_ok() && n.starts_with('-'))
+    }
+
+    #[test]
+    fn rotated_file_names() {
+        let dir = temp_dir("rotated-file-names");
+        let time = chrono::Local
+            .with_ymd_and_hms(2024, 8, 24, 9, 16, 41)
+            .unwrap();
+        let name = |output: &str| {
+            let (_, path) = create_rotated_file_at(&dir.join(output), time).unwrap();
+            path.file_name().unwrap().to_string_lossy().into_owned()
+        };
+
+        assert_eq!(name("site.taptap.gz"), "site-20240824T091641.taptap.gz");
+        assert_eq!(name("site.taptap.gz"), "site-20240824T091641-1.taptap.gz");
+        assert_eq!(name("site.taptap.gz"), "site-20240824T091641-2.taptap.gz");
+        assert_eq!(name("capture"), "capture-20240824T091641");
+        assert!(is_rotated_name("site-20240824T091641-2.taptap.gz"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = temp_dir("rotate-by-size");
+        let mut recorder =
+            Recorder::new(dir.join("site.taptap.gz"), "test".into(), 1, Duration::ZERO);
+        for slice in [b"abc", b"def", b"ghi"] {
+            recorder.write(slice, SystemTime::now()).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        // Each file exceeds one byte as soon as it's opened, so each slice gets a file of its own
+        let names = file_names(&dir);
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert!(
+            names.iter().all(|name| is_rotated_name(name)),
+            "{:?}",
+            names
+        );
+        assert_eq!(
+            recorded(&dir),
+            vec![b"abc".to_vec(), b"def".to_vec(), b"ghi".to_vec()]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotate_by_interval() {
+        let dir = temp_dir("rotate-by-interval");
+        let mut recorder = Recorder::new(
+            dir.join("site.taptap.gz"),
+            "test".into(),
+            0,
+            Duration::from_millis(100),
+        );
+        recorder.write(b"abc", SystemTime::now()).unwrap();
+        recorder.write(b"def", SystemTime::now()).unwrap();
+        std::thread::sleep(Duration::from_millis(150));
+        recorder.rotate_if_due().unwrap();
+        recorder.write(b"ghi", SystemTime::now()).unwrap();
+        recorder.finish().unwrap();
+
+        let names = file_names(&dir);
+        assert_eq!(names.len(), 2, "{:?}", names);
+        assert!(
+            names.iter().all(|name| is_rotated_name(name)),
+            "{:?}",
+            names
+        );
+        assert_eq!(recorded(&dir), vec![b"abcdef".to_vec(), b"ghi".to_vec()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn no_rotation() {
+        let dir = temp_dir("no-rotation");
+        let mut recorder =
+            Recorder::new(dir.join("site.taptap.gz"), "test".into(), 0, Duration::ZERO);
+        recorder.write(b"abc", SystemTime::now()).unwrap();
+        recorder.write(b"def", SystemTime::now()).unwrap();
+        recorder.finish().unwrap();
+
+        assert_eq!(file_names(&dir), vec!["site.taptap.gz"]);
+        assert_eq!(recorded(&dir), vec![b"abcdef".to_vec()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn start_time() {
+        let dir = temp_dir("start-time");
+        let mut recorder =
+            Recorder::new(dir.join("site.taptap.gz"), "test".into(), 0, Duration::ZERO);
+
+        // A slice read a moment before the file is opened
+        let timestamp = SystemTime::now() - Duration::from_millis(200);
+        recorder.write(b"abc", timestamp).unwrap();
+        recorder.finish().unwrap();
+
+        let reader = capture::Reader::new(File::open(dir.join("site.taptap.gz")).unwrap()).unwrap();
+        assert_eq!(
+            reader.metadata().unwrap().start_time,
+            chrono::DateTime::<chrono::Utc>::from(timestamp)
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
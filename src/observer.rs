@@ -30,9 +30,10 @@ use std::path::PathBuf;
 use std::time::SystemTime;
 
 pub mod event;
+pub use event::Event;
 
 mod persistent_state;
-use persistent_state::{PersistentState, PersistentStateEvent};
+pub use persistent_state::{PersistentState, PersistentStateEvent};
 
 mod node_table;
 use node_table::NodeTableBuilder;
@@ -40,15 +41,31 @@ use node_table::NodeTableBuilder;
 mod slot_clock;
 use slot_clock::SlotClock;
 
+mod clock;
+pub use clock::{Clock, FrameClock, ManualClock, SystemClock};
+
+mod event_sink;
+pub use event_sink::{EventSink, PrintEvents};
+
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "async")]
+pub use stream::EventStream;
+
 /// An observer, monitoring a controller interacting with one or more TAPs via an RS-485 interface.
+///
+/// Events are printed to standard output as JSON by default, or sent to any other `EventSink`.
 #[derive(Debug)]
-pub struct Observer {
+pub struct Observer<E = PrintEvents> {
+    source: Option<String>,
     persistent_file: String,
     persistent_state: PersistentState,
     enumeration_state: Option<EnumerationState>,
     captured_slot_counters: BTreeMap<GatewayID, SystemTime>,
     slot_clocks: BTreeMap<GatewayID, SlotClock>,
     node_table_builders: BTreeMap<GatewayID, NodeTableBuilder>,
+    clock: Box<dyn Clock + Send>,
+    events: E,
 }
 
 impl Default for Observer {
@@ -58,14 +75,53 @@ impl Default for Observer {
 }
 
 impl Observer {
+    /// Instantiate an observer which timestamps its observations with the time their frames were
+    /// received, using a `FrameClock`.
     pub fn new(persistent_file: String) -> Self {
+        Self::with_clock(persistent_file, FrameClock::default())
+    }
+
+    /// Instantiate an observer which timestamps its observations using `clock`.
+    ///
+    /// Use a `SystemClock` to timestamp observations with the time they are decoded instead, or a
+    /// `ManualClock` to control the time from outside.
+    pub fn with_clock<C: Clock + Send + 'static>(persistent_file: String, clock: C) -> Self {
+        Self::with_events(None, persistent_file, clock, PrintEvents)
+    }
+
+    /// Instantiate an observer for one of several sources, labelling its events with `source`.
+    ///
+    /// Gateway and node IDs are only unique within a bus, so each source needs its own observer,
+    /// and its own persistent file.
+    pub fn with_source<C: Clock + Send + 'static>(
+        source: String,
+        persistent_file: String,
+        clock: C,
+    ) -> Self {
+        Self::with_events(Some(source), persistent_file, clock, PrintEvents)
+    }
+}
+
+impl<E: EventSink> Observer<E> {
+    /// Instantiate an observer which sends its events to `events` instead of printing them.
+    ///
+    /// `source` labels the events as in `Observer::with_source()`, if given.
+    pub fn with_events<C: Clock + Send + 'static>(
+        source: Option<String>,
+        persistent_file: String,
+        clock: C,
+        events: E,
+    ) -> Self {
         let mut observer = Observer {
+            source,
             persistent_file,
             persistent_state: PersistentState::default(),
             enumeration_state: None,
             captured_slot_counters: Default::default(),
             slot_clocks: Default::default(),
             node_table_builders: Default::default(),
+            clock: Box::new(clock),
+            events,
         };
         observer.read_persistent_state();
         observer
@@ -74,7 +130,7 @@ impl Observer {
     // If a persistent state JSON file exists, prefer its contents over the provided
     // `persistent_state` argument. This allows the observer to restore previously
     // captured infrastructure information across runs.
-    pub fn read_persistent_state(&mut self) -> () {
+    pub fn read_persistent_state(&mut self) {
         if self.persistent_file.is_empty() {
             log::info!("persistent file is not specified, will not keep persistent state");
             return;
@@ -98,9 +154,9 @@ impl Observer {
                     "persistent state successfully loaded from persistent file {}",
                     file_path.display()
                 );
-                // Print out infrastructure event
-                let infrastructure_event = PersistentStateEvent::from(&self.persistent_state);
-                println!("{}", serde_json::to_string(&infrastructure_event).unwrap());
+                let infrastructure_event = self.infrastructure_event();
+                self.events
+                    .event(Event::InfrastructureReport(infrastructure_event));
             }
             Err(e) => {
                 log::warn!(
@@ -114,14 +170,15 @@ impl Observer {
 
     /// Write the current `persistent_state` to disk as JSON.
     ///
-    /// Writes atomically by writing to a temporary file and renaming it into place.
-    pub fn write_persistent_state(&self) -> () {
-        let infrastructure_event = PersistentStateEvent::from(&self.persistent_state);
-        match serde_json::to_string(&infrastructure_event) {
-            Ok(event_str) => println!("{}", event_str),
-            Err(e) => {
-                log::error!("Failed to serialize infrastructure event: {}", e);
-            }
+    /// Writes atomically by writing to a temporary file and renaming it into place. The new state
+    /// is also sent out as an infrastructure event.
+    pub fn write_persistent_state(&mut self) {
+        let infrastructure_event = self.infrastructure_event();
+        self.events
+            .event(Event::InfrastructureReport(infrastructure_event));
+
+        if self.persistent_file.is_empty() {
+            return;
         }
 
         let file_path = PathBuf::from(&self.persistent_file);
@@ -178,10 +235,6 @@ impl Observer {
             return;
         };
 
-        // Print out infrastructure event
-        let infrastructure_event = PersistentStateEvent::from(&self.persistent_state);
-        println!("{}", serde_json::to_string(&infrastructure_event).unwrap());
-
         log::debug!(
             "Successfully wrote persistent state to persistent file {}",
             file_path.display()
@@ -191,10 +244,30 @@ impl Observer {
     pub fn persistent_state(&self) -> &PersistentState {
         &self.persistent_state
     }
+
+    /// The sink receiving this observer's events.
+    pub fn events(&self) -> &E {
+        &self.events
+    }
+
+    pub fn events_mut(&mut self) -> &mut E {
+        &mut self.events
+    }
+
+    /// The label added to events from this observer, if it is one of several.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    fn infrastructure_event(&self) -> PersistentStateEvent {
+        let mut event = PersistentStateEvent::from(&self.persistent_state);
+        event.source = self.source.clone();
+        event
+    }
 }
 
-impl gateway::transport::Sink for Observer {
-    fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID) {
+impl<E: EventSink> gateway::transport::Sink for Observer<E> {
+    fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID, _timestamp: SystemTime) {
         self.enumeration_state = Some(EnumerationState {
             enumeration_gateway_id,
             gateway_identities: Default::default(),
@@ -202,7 +275,12 @@ impl gateway::transport::Sink for Observer {
         });
     }
 
-    fn gateway_identity_observed(&mut self, gateway_id: GatewayID, address: LongAddress) {
+    fn gateway_identity_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        address: LongAddress,
+        _timestamp: SystemTime,
+    ) {
         if let Some(enumeration_state) = self.enumeration_state.as_mut() {
             // We're enumerating
             // Delegate
@@ -216,7 +294,12 @@ impl gateway::transport::Sink for Observer {
         }
     }
 
-    fn gateway_version_observed(&mut self, gateway_id: GatewayID, version: &str) {
+    fn gateway_version_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        version: &str,
+        _timestamp: SystemTime,
+    ) {
         let version = version.to_owned();
 
         if let Some(enumeration_state) = self.enumeration_state.as_mut() {
@@ -231,7 +314,7 @@ impl gateway::transport::Sink for Observer {
         }
     }
 
-    fn enumeration_ended(&mut self, _gateway_id: GatewayID) {
+    fn enumeration_ended(&mut self, _gateway_id: GatewayID, _timestamp: SystemTime) {
         // We're done enumerating
         // Did we catch the whole exchange?
         if let Some(enumeration_state) = self.enumeration_state.take() {
@@ -243,12 +326,18 @@ impl gateway::transport::Sink for Observer {
         }
     }
 
-    fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID) {
+    fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID, timestamp: SystemTime) {
+        self.clock.frame_received(timestamp);
         self.captured_slot_counters
-            .insert(gateway_id, SystemTime::now());
+            .insert(gateway_id, self.clock.now());
     }
 
-    fn gateway_slot_counter_observed(&mut self, gateway_id: GatewayID, slot_counter: SlotCounter) {
+    fn gateway_slot_counter_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        slot_counter: SlotCounter,
+        _timestamp: SystemTime,
+    ) {
         let Some(time) = self.captured_slot_counters.remove(&gateway_id) else {
             return;
         };
@@ -270,6 +359,7 @@ impl gateway::transport::Sink for Observer {
         _gateway_id: GatewayID,
         _packet_header: &ReceivedPacketHeader,
         _packet_data: &[u8],
+        _timestamp: SystemTime,
     ) {
     }
 
@@ -278,16 +368,18 @@ impl gateway::transport::Sink for Observer {
         _gateway_id: GatewayID,
         _command_request: (PacketType, &[u8]),
         _command_response: (PacketType, &[u8]),
+        _timestamp: SystemTime,
     ) {
     }
 }
 
-impl pv::application::Sink for Observer {
+impl<E: EventSink> pv::application::Sink for Observer<E> {
     fn string_request(
         &mut self,
         _gateway_id: GatewayID,
         _pv_node_id: NodeID,
         _string_request: &str,
+        _timestamp: SystemTime,
     ) {
     }
 
@@ -296,6 +388,7 @@ impl pv::application::Sink for Observer {
         _gateway_id: GatewayID,
         _pv_node_id: NodeID,
         _string_response: &str,
+        _timestamp: SystemTime,
     ) {
     }
 
@@ -304,6 +397,7 @@ impl pv::application::Sink for Observer {
         gateway_id: GatewayID,
         start_address: NodeAddress,
         nodes: &[NodeTableResponseEntry],
+        _timestamp: SystemTime,
     ) {
         let builder = self.node_table_builders.entry(gateway_id).or_default();
 
@@ -320,6 +414,7 @@ impl pv::application::Sink for Observer {
         _gateway_id: GatewayID,
         _pv_node_id: NodeID,
         _topology_report: &TopologyReport,
+        _timestamp: SystemTime,
     ) {
     }
 
@@ -328,6 +423,7 @@ impl pv::application::Sink for Observer {
         gateway_id: GatewayID,
         node_id: NodeID,
         power_report: &pv::application::PowerReport,
+        _timestamp: SystemTime,
     ) {
         let Some(slot_clock) = self.slot_clocks.get(&gateway_id) else {
             log::error!(
@@ -338,7 +434,8 @@ impl pv::application::Sink for Observer {
             return;
         };
 
-        let Ok(event) = event::PowerReportEvent::new(gateway_id, node_id, slot_clock, power_report)
+        let Ok(mut event) =
+            event::PowerReportEvent::new(gateway_id, node_id, slot_clock, power_report)
         else {
             log::error!(
                 "discarding power report from gateway {:?} due to invalid slot counter: {:?}",
@@ -347,8 +444,9 @@ impl pv::application::Sink for Observer {
             );
             return;
         };
+        event.source = self.source.clone();
 
-        println!("{}", serde_json::to_string(&event).unwrap());
+        self.events.event(Event::PowerReport(event));
     }
 }
 
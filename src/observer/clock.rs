@@ -0,0 +1,108 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A source of the current time, consulted by an `Observer` to timestamp what it sees.
+pub trait Clock: Debug {
+    fn now(&self) -> SystemTime;
+
+    /// Note that the frame being handled was received at `timestamp`.
+    ///
+    /// The `Observer` calls this before consulting the clock. Clocks which keep their own time
+    /// ignore it.
+    fn frame_received(&mut self, _timestamp: SystemTime) {}
+}
+
+/// A `Clock` which reads the system's wall clock.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A `Clock` which reports whatever time it was last set to.
+///
+/// Clones share the same time, so a caller can keep a handle to drive the clock while an
+/// `Observer` holds another, e.g. to apply the timestamps of capture records as they are replayed.
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl ManualClock {
+    pub fn new(time: SystemTime) -> Self {
+        Self(Arc::new(Mutex::new(time)))
+    }
+
+    /// Set the time reported by this clock and all its clones.
+    pub fn set(&self, time: SystemTime) {
+        *self.0.lock().unwrap() = time;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// A `Clock` which reports the time the frame being handled was received.
+///
+/// This is the `Observer`'s default. Feeding the link layer `Receiver` with `extend_from_slice_at()`
+/// and capture record timestamps reproduces the original times when replaying a capture.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameClock(SystemTime);
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for FrameClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+
+    fn frame_received(&mut self, timestamp: SystemTime) {
+        self.0 = timestamp;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::default();
+        let mut handle = clock.clone();
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1723500000);
+        handle.set(t);
+        assert_eq!(clock.now(), t);
+
+        // Frame timestamps don't move it
+        handle.frame_received(t + Duration::from_secs(1));
+        assert_eq!(clock.now(), t);
+    }
+
+    #[test]
+    fn frame_clock() {
+        let mut clock = FrameClock::default();
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1723500000);
+        clock.frame_received(t);
+        assert_eq!(clock.now(), t);
+    }
+}
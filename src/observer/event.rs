@@ -6,10 +6,14 @@ use crate::pv::physical::RSSI;
 use chrono::{DateTime, Local};
 
 /// An event produced by an observer.
+///
+/// Events serialize to the JSON objects printed by `taptap observe`, which tell their kind apart by
+/// `event_type`.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
-#[serde(rename = "snake_case")]
+#[serde(untagged)]
 pub enum Event {
     PowerReport(PowerReportEvent),
+    InfrastructureReport(PersistentStateEvent),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -51,6 +55,9 @@ pub struct Node {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct PowerReportEvent {
     pub event_type: String,
+    /// The source on which the power report was received, when observing several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
     /// The gateway through which the power report was received.
     pub gateway: GatewayID,
     /// The node sending the power report.
@@ -87,6 +94,7 @@ impl PowerReportEvent {
 
         Ok(Self {
             event_type: "power_report".to_string(),
+            source: None,
             gateway,
             node,
             timestamp: timestamp.into(),
@@ -111,7 +119,7 @@ mod tests {
         let gateway = GatewayID::try_from(1).unwrap();
         let node = NodeID::try_from(1).unwrap();
         let rssi = RSSI(100);
-        let timestamp = SystemTime::now();
+        let timestamp = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1723500000);
         let slot_counter = SlotCounter::from(0);
         let slot_clock = SlotClock::new(slot_counter, timestamp).unwrap();
 
@@ -130,6 +138,7 @@ mod tests {
         let actual = serde_json::to_string(&power_report_event).unwrap();
         let expected = serde_json::to_string(&PowerReportEvent {
             event_type,
+            source: None,
             gateway,
             node,
             timestamp: timestamp.into(),
@@ -0,0 +1,30 @@
+use super::Event;
+
+/// A destination for the events produced by an `Observer`.
+pub trait EventSink {
+    fn event(&mut self, event: Event);
+}
+
+/// Print each event to standard output as a line of JSON, like `taptap observe`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PrintEvents;
+
+impl EventSink for PrintEvents {
+    fn event(&mut self, event: Event) {
+        println!("{}", serde_json::to_string(&event).unwrap());
+    }
+}
+
+/// Collect events, e.g. to examine them after feeding the observer a capture.
+impl EventSink for Vec<Event> {
+    fn event(&mut self, event: Event) {
+        self.push(event);
+    }
+}
+
+/// Pass events to another thread. Events are discarded once the receiver is gone.
+impl EventSink for std::sync::mpsc::Sender<Event> {
+    fn event(&mut self, event: Event) {
+        self.send(event).ok();
+    }
+}
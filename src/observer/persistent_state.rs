@@ -33,6 +33,9 @@ pub struct PersistentStateEventNode {
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PersistentStateEvent {
     pub event_type: String,
+    /// The source on which this was observed, when observing several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
     pub gateways: BTreeMap<GatewayID, PersistentStateEventGateway>,
     pub nodes: BTreeMap<GatewayID, BTreeMap<NodeID, PersistentStateEventNode>>,
 }
@@ -99,6 +102,7 @@ impl From<&PersistentState> for PersistentStateEvent {
 
         PersistentStateEvent {
             event_type,
+            source: None,
             gateways,
             nodes,
         }
@@ -121,6 +125,9 @@ impl JsonSchema for PersistentStateEvent {
                 "event_type": {
                     "type": "string"
                 },
+                "source": {
+                    "type": "string"
+                },
                 "gateways": {
                     "type": "object",
                     "properties": {
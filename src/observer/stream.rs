@@ -0,0 +1,124 @@
+use super::{Event, EventSink, FrameClock, Observer};
+use crate::config::{AsyncSourceReader, ConnectionEvent, SourceError};
+use crate::{gateway, pv};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+type Item = Result<Event, SourceError>;
+
+/// The events observed on a source, as a `Stream`.
+///
+/// The source is read and decoded by a tokio task, using the same receivers and `Observer` as
+/// `taptap observe`. Observations are timestamped with the time each slice of bytes was received.
+///
+/// The stream ends at the end of a source which `ends_at_eof()`. If the source can't be read, the
+/// `SourceError` is the last item. Dropping the stream stops the task.
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: mpsc::UnboundedReceiver<Item>,
+    task: JoinHandle<()>,
+}
+
+impl EventStream {
+    /// Start observing `reader`. This must be called from within a tokio runtime.
+    ///
+    /// `source` and `persistent_file` are passed on to `Observer::with_events()`.
+    pub fn new<F>(
+        reader: AsyncSourceReader<F>,
+        source: Option<String>,
+        persistent_file: String,
+    ) -> Self
+    where
+        F: FnMut(&ConnectionEvent) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(observe(reader, source, persistent_file, sender));
+        Self { receiver, task }
+    }
+}
+
+impl futures_core::Stream for EventStream {
+    type Item = Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Debug)]
+struct Events(mpsc::UnboundedSender<Item>);
+
+impl EventSink for Events {
+    fn event(&mut self, event: Event) {
+        self.0.send(Ok(event)).ok();
+    }
+}
+
+async fn observe<F: FnMut(&ConnectionEvent)>(
+    mut reader: AsyncSourceReader<F>,
+    source: Option<String>,
+    persistent_file: String,
+    sender: mpsc::UnboundedSender<Item>,
+) {
+    let observer = Observer::with_events(
+        source,
+        persistent_file,
+        FrameClock::default(),
+        Events(sender.clone()),
+    );
+    let mut rx = gateway::link::Receiver::new(gateway::transport::Receiver::new(
+        pv::application::Receiver::new(observer),
+    ));
+
+    while !sender.is_closed() {
+        match reader.read().await {
+            Ok(Some((slice, timestamp))) => rx.extend_from_slice_at(slice, timestamp),
+            Ok(None) => return,
+            Err(e) => {
+                sender.send(Err(e)).ok();
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ReconnectConfig, SourceConfig, StreamInput, StreamSourceConfig};
+    use futures_core::Stream;
+    use std::future::poll_fn;
+
+    #[tokio::test]
+    async fn enumeration_sequence() {
+        let path = std::env::temp_dir().join(format!("taptap-stream-{}.bin", std::process::id()));
+        std::fs::write(&path, crate::test_data::ENUMERATION_SEQUENCE).unwrap();
+
+        let source = SourceConfig::Stream(StreamSourceConfig {
+            input: StreamInput::File(path.clone()),
+            follow: false,
+        });
+        let reader = AsyncSourceReader::new(source, ReconnectConfig::default());
+        let mut stream = EventStream::new(reader, Some("cca".into()), String::new());
+
+        let mut events = Vec::new();
+        while let Some(item) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            events.push(item.unwrap());
+        }
+        std::fs::remove_file(&path).ok();
+
+        let Some(Event::InfrastructureReport(report)) = events.last() else {
+            panic!("expected an infrastructure report, got {:?}", events);
+        };
+        assert_eq!(report.source.as_deref(), Some("cca"));
+        assert_eq!(report.gateways.len(), 2);
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use std::time::Duration;
 
 #[test]
 fn enumeration_sequence() {
@@ -63,3 +64,69 @@ fn enumeration_sequence() {
         ),]
     );
 }
+
+#[test]
+fn slot_clock_uses_frame_timestamps() {
+    let mut observer = Observer::default();
+    let gateway_id = GatewayID::try_from(0x1201).unwrap();
+
+    // The slot counter is captured at a historical time, and observed later
+    let captured = SystemTime::UNIX_EPOCH + Duration::from_secs(1723500000);
+    gateway::transport::Sink::gateway_slot_counter_captured(&mut observer, gateway_id, captured);
+    gateway::transport::Sink::gateway_slot_counter_observed(
+        &mut observer,
+        gateway_id,
+        SlotCounter::from(0x4000),
+        captured + Duration::from_millis(30),
+    );
+
+    assert_eq!(
+        observer.slot_clocks[&gateway_id].get(SlotCounter::from(0x4000)),
+        Ok(captured)
+    );
+    assert_eq!(
+        observer.slot_clocks[&gateway_id].get(SlotCounter::from(0x0000)),
+        Ok(captured - Duration::from_secs(60))
+    );
+}
+
+#[test]
+fn slot_clock_uses_clock() {
+    let clock = ManualClock::default();
+    let mut observer = Observer::with_clock(String::new(), clock.clone());
+    let gateway_id = GatewayID::try_from(0x1201).unwrap();
+
+    // The slot counter is captured at a time set from outside, whatever the frame's timestamp
+    let captured = SystemTime::UNIX_EPOCH + Duration::from_secs(1723500000);
+    clock.set(captured);
+    gateway::transport::Sink::gateway_slot_counter_captured(
+        &mut observer,
+        gateway_id,
+        SystemTime::UNIX_EPOCH,
+    );
+    clock.set(captured + Duration::from_millis(30));
+    gateway::transport::Sink::gateway_slot_counter_observed(
+        &mut observer,
+        gateway_id,
+        SlotCounter::from(0x4000),
+        SystemTime::UNIX_EPOCH,
+    );
+
+    assert_eq!(
+        observer.slot_clocks[&gateway_id].get(SlotCounter::from(0x4000)),
+        Ok(captured)
+    );
+}
+
+#[test]
+fn source_label() {
+    let observer = Observer::with_source("cca".into(), String::new(), FrameClock::default());
+    assert_eq!(observer.source(), Some("cca"));
+
+    let event = serde_json::to_value(observer.infrastructure_event()).unwrap();
+    assert_eq!(event["source"], "cca");
+
+    // A lone observer's events are unlabelled, as they always were
+    let event = serde_json::to_value(Observer::default().infrastructure_event()).unwrap();
+    assert!(event.get("source").is_none());
+}
@@ -0,0 +1,256 @@
+//! Exporting traffic as pcapng, for analysis with Wireshark and similar tools.
+//!
+//! A [`Writer`] writes one packet per link layer [`Frame`] on its first interface, using the link
+//! type [`LINKTYPE_FRAMES`]. Each packet holds the unescaped address, frame type, payload and CRC,
+//! as returned by [`Frame::unescaped()`]. Raw bytes, exactly as received, can also be written on a
+//! second interface using [`LINKTYPE_BYTES`].
+//!
+//! Both link types are from the range reserved for private use, so Wireshark needs a dissector
+//! configured for them in its `DLT_USER` preferences.
+
+use crate::capture::Direction;
+use crate::gateway::link::Frame;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The link type of link layer frames: `LINKTYPE_USER0`.
+pub const LINKTYPE_FRAMES: u16 = 147;
+
+/// The link type of raw bytes: `LINKTYPE_USER1`.
+pub const LINKTYPE_BYTES: u16 = 148;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END_OF_OPT: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_DESCRIPTION: u16 = 3;
+const OPT_EPB_FLAGS: u16 = 2;
+
+/// Writes a pcapng section of frames, and optionally raw bytes, received from one source.
+///
+/// Timestamps have the default pcapng resolution of one microsecond.
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    inner: W,
+    source: String,
+    bytes_interface: Option<u32>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Start a pcapng file, naming the interfaces after `source`.
+    pub fn new(mut inner: W, source: impl Into<String>) -> std::io::Result<Self> {
+        let source = source.into();
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes()); // major version
+        body.extend_from_slice(&0u16.to_le_bytes()); // minor version
+        body.extend_from_slice(&(-1i64).to_le_bytes()); // section length, unspecified
+        let userappl = format!("taptap {}", env!("CARGO_PKG_VERSION"));
+        push_option(&mut body, OPT_SHB_USERAPPL, userappl.as_bytes());
+        push_option(&mut body, OPT_END_OF_OPT, &[]);
+        write_block(&mut inner, SECTION_HEADER_BLOCK, &body)?;
+
+        write_interface(
+            &mut inner,
+            LINKTYPE_FRAMES,
+            &source,
+            "TAP link layer frames",
+        )?;
+
+        Ok(Self {
+            inner,
+            source,
+            bytes_interface: None,
+        })
+    }
+
+    /// Write a frame received at `timestamp`.
+    pub fn write_frame(&mut self, frame: &Frame, timestamp: SystemTime) -> std::io::Result<()> {
+        write_packet(&mut self.inner, 0, timestamp, &frame.unescaped(), None)
+    }
+
+    /// Write raw bytes received, or transmitted, at `timestamp`.
+    ///
+    /// The interface for raw bytes is described when they are first written.
+    pub fn write_bytes(
+        &mut self,
+        bytes: &[u8],
+        timestamp: SystemTime,
+        direction: Option<Direction>,
+    ) -> std::io::Result<()> {
+        let interface = match self.bytes_interface {
+            Some(interface) => interface,
+            None => {
+                write_interface(
+                    &mut self.inner,
+                    LINKTYPE_BYTES,
+                    &self.source,
+                    "TAP raw bytes",
+                )?;
+                *self.bytes_interface.insert(1)
+            }
+        };
+        write_packet(&mut self.inner, interface, timestamp, bytes, direction)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+fn write_interface<W: Write>(
+    writer: &mut W,
+    link_type: u16,
+    name: &str,
+    description: &str,
+) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&link_type.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes()); // reserved
+    body.extend_from_slice(&0u32.to_le_bytes()); // snap length, unlimited
+    push_option(&mut body, OPT_IF_NAME, name.as_bytes());
+    push_option(&mut body, OPT_IF_DESCRIPTION, description.as_bytes());
+    push_option(&mut body, OPT_END_OF_OPT, &[]);
+    write_block(writer, INTERFACE_DESCRIPTION_BLOCK, &body)
+}
+
+fn write_packet<W: Write>(
+    writer: &mut W,
+    interface: u32,
+    timestamp: SystemTime,
+    data: &[u8],
+    direction: Option<Direction>,
+) -> std::io::Result<()> {
+    let micros = timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+
+    let mut body = Vec::with_capacity(20 + data.len() + 16);
+    body.extend_from_slice(&interface.to_le_bytes());
+    body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(micros as u32).to_le_bytes());
+    body.extend_from_slice(&(data.len() as u32).to_le_bytes()); // captured length
+    body.extend_from_slice(&(data.len() as u32).to_le_bytes()); // original length
+    body.extend_from_slice(data);
+    pad(&mut body);
+
+    if let Some(direction) = direction {
+        let flags: u32 = match direction {
+            Direction::Rx => 0b01,
+            Direction::Tx => 0b10,
+        };
+        push_option(&mut body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+        push_option(&mut body, OPT_END_OF_OPT, &[]);
+    }
+
+    write_block(writer, ENHANCED_PACKET_BLOCK, &body)
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+/// Pad `body` to a multiple of 32 bits.
+fn pad(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> std::io::Result<()> {
+    debug_assert_eq!(body.len() % 4, 0);
+    let total_length = (12 + body.len()) as u32;
+
+    let mut block = Vec::with_capacity(total_length as usize);
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&total_length.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&total_length.to_le_bytes());
+    writer.write_all(&block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::link::{Address, GatewayID, Type};
+    use std::time::Duration;
+
+    /// Split a pcapng file into its blocks' types and bodies.
+    fn blocks(mut file: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut blocks = Vec::new();
+        while !file.is_empty() {
+            let block_type = u32::from_le_bytes(file[0..4].try_into().unwrap());
+            let length = u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize;
+            assert_eq!(length % 4, 0);
+            assert_eq!(&file[length - 4..length], &file[4..8]);
+            blocks.push((block_type, &file[8..length - 4]));
+            file = &file[length..];
+        }
+        blocks
+    }
+
+    #[test]
+    fn frames_and_bytes() {
+        let frame = Frame {
+            address: Address::From(GatewayID::try_from(0x1201).unwrap()),
+            frame_type: Type::RECEIVE_RESPONSE,
+            payload: b"\x00\xFF\x7C\xDB\xC2".as_slice().into(),
+        };
+        let t = UNIX_EPOCH + Duration::from_micros(1723500000123456);
+
+        let mut writer = Writer::new(Vec::new(), "tcp 192.0.2.1:502").unwrap();
+        writer.write_frame(&frame, t).unwrap();
+        writer
+            .write_bytes(&frame.encode(), t, Some(Direction::Rx))
+            .unwrap();
+        let file = writer.into_inner();
+
+        let blocks = blocks(&file);
+        let types = blocks.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                SECTION_HEADER_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+            ]
+        );
+
+        // The interfaces are named after the source
+        let (_, frames_interface) = blocks[1];
+        assert_eq!(&frames_interface[0..2], &LINKTYPE_FRAMES.to_le_bytes());
+        assert_eq!(&frames_interface[8..12], &[2, 0, 17, 0]);
+        assert_eq!(&frames_interface[12..29], b"tcp 192.0.2.1:502");
+        let (_, bytes_interface) = blocks[3];
+        assert_eq!(&bytes_interface[0..2], &LINKTYPE_BYTES.to_le_bytes());
+
+        // Packets carry the timestamp and content
+        let (_, packet) = blocks[2];
+        assert_eq!(&packet[0..4], &0u32.to_le_bytes());
+        let micros = (u32::from_le_bytes(packet[4..8].try_into().unwrap()) as u64) << 32
+            | u32::from_le_bytes(packet[8..12].try_into().unwrap()) as u64;
+        assert_eq!(micros, 1723500000123456);
+        assert_eq!(&packet[12..16], &11u32.to_le_bytes());
+        assert_eq!(&packet[20..31], frame.unescaped().as_slice());
+
+        let (_, packet) = blocks[4];
+        assert_eq!(&packet[0..4], &1u32.to_le_bytes());
+        let length = frame.encode().len();
+        assert_eq!(&packet[20..20 + length], frame.encode().as_slice());
+        let options = &packet[20 + length.next_multiple_of(4)..];
+        assert_eq!(options, &[2, 0, 4, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
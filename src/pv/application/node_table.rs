@@ -42,12 +42,12 @@ mod tests {
 
     #[test]
     fn response() {
-        let response = NodeTableResponse::ref_from_bytes(b"\x00\x00").unwrap();
+        let response = NodeTableResponse::ref_from_bytes(b"\x00\x00\x00\x00").unwrap();
         assert_eq!(response.entries_count.get(), 0);
         assert_eq!(response.entries.len(), 0);
 
         let response = NodeTableResponse::ref_from_bytes(
-            b"\x00\x0C\x04\xC0\x5B\x40\x00\xA2\x34\x6F\x00\x02\x04\xC0\x5B\x40\x00\xA2\x34\x71\x00\x03",
+            b"\x00\x01\x00\x0C\x04\xC0\x5B\x40\x00\xA2\x34\x6F\x00\x02\x04\xC0\x5B\x40\x00\xA2\x34\x71\x00\x03",
         ).unwrap();
         assert_eq!(response.entries_count.get(), 0x000c);
         assert_eq!(response.entries.len(), 2);
@@ -1,17 +1,35 @@
 use super::*;
 use crate::gateway::GatewayID;
-use crate::pv::network::{NodeAddress, ReceivedPacketHeader};
+use crate::pv::network::{NodeAddress, ReceivedPacketHeader, ReceivedPackets};
 use crate::pv::{LongAddress, NodeID, PacketType, SlotCounter};
 use crate::{gateway, pv};
+use std::time::SystemTime;
 
+/// An object which handles reception callbacks.
+///
+/// Each callback is passed the `timestamp` of the link layer frame it stems from, as for
+/// `gateway::transport::Sink`.
 pub trait Sink {
-    fn string_request(&mut self, gateway_id: GatewayID, pv_node_id: pv::NodeID, request: &str);
-    fn string_response(&mut self, gateway_id: GatewayID, pv_node_id: pv::NodeID, response: &str);
+    fn string_request(
+        &mut self,
+        gateway_id: GatewayID,
+        pv_node_id: pv::NodeID,
+        request: &str,
+        timestamp: SystemTime,
+    );
+    fn string_response(
+        &mut self,
+        gateway_id: GatewayID,
+        pv_node_id: pv::NodeID,
+        response: &str,
+        timestamp: SystemTime,
+    );
     fn node_table_page(
         &mut self,
         gateway_id: GatewayID,
         start_address: NodeAddress,
         nodes: &[NodeTableResponseEntry],
+        timestamp: SystemTime,
     );
 
     fn topology_report(
@@ -19,12 +37,14 @@ pub trait Sink {
         gateway_id: GatewayID,
         pv_node_id: pv::NodeID,
         topology_report: &TopologyReport,
+        timestamp: SystemTime,
     );
     fn power_report(
         &mut self,
         gateway_id: GatewayID,
         pv_node_id: pv::NodeID,
         power_report: &PowerReport,
+        timestamp: SystemTime,
     );
 }
 
@@ -73,7 +93,13 @@ impl<S: gateway::transport::Sink + Sink> Receiver<S> {
         &self.counters
     }
 
-    fn node_table_command(&mut self, gateway_id: GatewayID, request: &[u8], response: &[u8]) {
+    fn node_table_command(
+        &mut self,
+        gateway_id: GatewayID,
+        request: &[u8],
+        response: &[u8],
+        timestamp: SystemTime,
+    ) {
         let Ok(request) = NodeTableRequest::ref_from_bytes(request) else {
             self.counters.invalid_node_table_requests += 1;
             return;
@@ -90,10 +116,16 @@ impl<S: gateway::transport::Sink + Sink> Receiver<S> {
         };
 
         self.sink
-            .node_table_page(gateway_id, request.start_at, &response.entries);
+            .node_table_page(gateway_id, request.start_at, &response.entries, timestamp);
     }
 
-    fn string_command(&mut self, gateway_id: GatewayID, request: &[u8], response: &[u8]) {
+    fn string_command(
+        &mut self,
+        gateway_id: GatewayID,
+        request: &[u8],
+        response: &[u8],
+        timestamp: SystemTime,
+    ) {
         let Ok((node, request)) = NodeAddress::ref_from_prefix(request) else {
             self.counters.invalid_string_commands += 1;
             return;
@@ -115,34 +147,54 @@ impl<S: gateway::transport::Sink + Sink> Receiver<S> {
 
         self.counters.string_commands += 1;
 
-        self.sink.string_request(gateway_id, node, request);
+        self.sink
+            .string_request(gateway_id, node, request, timestamp);
     }
 }
 
 impl<S: gateway::transport::Sink + Sink> gateway::transport::Sink for Receiver<S> {
-    fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID) {
-        self.sink.enumeration_started(enumeration_gateway_id)
+    fn enumeration_started(&mut self, enumeration_gateway_id: GatewayID, timestamp: SystemTime) {
+        self.sink
+            .enumeration_started(enumeration_gateway_id, timestamp)
     }
 
-    fn gateway_identity_observed(&mut self, gateway_id: GatewayID, address: LongAddress) {
-        self.sink.gateway_identity_observed(gateway_id, address)
+    fn gateway_identity_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        address: LongAddress,
+        timestamp: SystemTime,
+    ) {
+        self.sink
+            .gateway_identity_observed(gateway_id, address, timestamp)
     }
 
-    fn gateway_version_observed(&mut self, gateway_id: GatewayID, version: &str) {
-        self.sink.gateway_version_observed(gateway_id, version)
+    fn gateway_version_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        version: &str,
+        timestamp: SystemTime,
+    ) {
+        self.sink
+            .gateway_version_observed(gateway_id, version, timestamp)
     }
 
-    fn enumeration_ended(&mut self, gateway_id: GatewayID) {
-        self.sink.enumeration_ended(gateway_id)
+    fn enumeration_ended(&mut self, gateway_id: GatewayID, timestamp: SystemTime) {
+        self.sink.enumeration_ended(gateway_id, timestamp)
     }
 
-    fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID) {
-        self.sink.gateway_slot_counter_captured(gateway_id)
+    fn gateway_slot_counter_captured(&mut self, gateway_id: GatewayID, timestamp: SystemTime) {
+        self.sink
+            .gateway_slot_counter_captured(gateway_id, timestamp)
     }
 
-    fn gateway_slot_counter_observed(&mut self, gateway_id: GatewayID, slot_counter: SlotCounter) {
+    fn gateway_slot_counter_observed(
+        &mut self,
+        gateway_id: GatewayID,
+        slot_counter: SlotCounter,
+        timestamp: SystemTime,
+    ) {
         self.sink
-            .gateway_slot_counter_observed(gateway_id, slot_counter)
+            .gateway_slot_counter_observed(gateway_id, slot_counter, timestamp)
     }
 
     fn packet_received(
@@ -150,8 +202,10 @@ impl<S: gateway::transport::Sink + Sink> gateway::transport::Sink for Receiver<S
         gateway_id: GatewayID,
         header: &ReceivedPacketHeader,
         data: &[u8],
+        timestamp: SystemTime,
     ) {
-        self.sink.packet_received(gateway_id, header, data);
+        self.sink
+            .packet_received(gateway_id, header, data, timestamp);
 
         let Ok(node_id) = pv::NodeID::try_from(header.node_address) else {
             self.counters.invalid_received_packet_node_ids += 1;
@@ -162,7 +216,8 @@ impl<S: gateway::transport::Sink + Sink> gateway::transport::Sink for Receiver<S
             PacketType::STRING_RESPONSE => {
                 if let Ok(response) = std::str::from_utf8(data) {
                     self.counters.string_responses += 1;
-                    self.sink.string_response(gateway_id, node_id, response);
+                    self.sink
+                        .string_response(gateway_id, node_id, response, timestamp);
                 } else {
                     self.counters.invalid_string_responses += 1;
                 }
@@ -171,7 +226,7 @@ impl<S: gateway::transport::Sink + Sink> gateway::transport::Sink for Receiver<S
                 if let Ok(topology_report) = TopologyReport::ref_from_bytes(data) {
                     self.counters.topology_reports += 1;
                     self.sink
-                        .topology_report(gateway_id, node_id, topology_report);
+                        .topology_report(gateway_id, node_id, topology_report, timestamp);
                 } else {
                     self.counters.invalid_topology_reports += 1;
                 }
@@ -179,7 +234,8 @@ impl<S: gateway::transport::Sink + Sink> gateway::transport::Sink for Receiver<S
             PacketType::POWER_REPORT => {
                 if let Ok(power_report) = PowerReport::ref_from_bytes(data) {
                     self.counters.power_reports += 1;
-                    self.sink.power_report(gateway_id, node_id, power_report);
+                    self.sink
+                        .power_report(gateway_id, node_id, power_report, timestamp);
                 } else {
                     self.counters.invalid_power_reports += 1;
                 }
@@ -188,21 +244,33 @@ impl<S: gateway::transport::Sink + Sink> gateway::transport::Sink for Receiver<S
         }
     }
 
+    fn receive_response_retransmitted(
+        &mut self,
+        gateway_id: GatewayID,
+        packets: ReceivedPackets<'_>,
+        timestamp: SystemTime,
+    ) {
+        self.sink
+            .receive_response_retransmitted(gateway_id, packets, timestamp)
+    }
+
     fn command_executed(
         &mut self,
         gateway_id: GatewayID,
         request: (PacketType, &[u8]),
         response: (PacketType, &[u8]),
+        timestamp: SystemTime,
     ) {
-        self.sink.command_executed(gateway_id, request, response);
+        self.sink
+            .command_executed(gateway_id, request, response, timestamp);
 
         match (request.0, response.0) {
             (PacketType::NODE_TABLE_REQUEST, PacketType::NODE_TABLE_RESPONSE) => {
-                self.node_table_command(gateway_id, request.1, response.1);
+                self.node_table_command(gateway_id, request.1, response.1, timestamp);
             }
 
             (PacketType::STRING_REQUEST, PacketType::STRING_RESPONSE) => {
-                self.string_command(gateway_id, request.1, response.1);
+                self.string_command(gateway_id, request.1, response.1, timestamp);
             }
             //(PacketType::BROADCAST, PacketType::BROADCAST_ACK) => {}
             (
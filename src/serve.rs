@@ -0,0 +1,236 @@
+//! Sharing one bus connection with other consumers.
+//!
+//! Many RS-485-to-TCP converters accept only a single client. A [`Server`] re-broadcasts the bytes
+//! received on that one connection to any number of TCP clients, so that e.g. `peek-frames` can
+//! run elsewhere while `observe` keeps running.
+
+use crate::capture;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// How many slices may be waiting for a client before it is considered too slow and disconnected.
+const CLIENT_QUEUE_LENGTH: usize = 1024;
+
+/// How long a write to a client may block before the client is considered gone.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The format in which a `Server` sends data to its clients.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Format {
+    /// The bytes as received, exactly like a direct connection to the bus.
+    #[default]
+    Raw,
+    /// A stream of timestamped `capture` records, so that clients keep the original timing.
+    Capture,
+}
+
+type Slice = (Arc<[u8]>, SystemTime);
+
+/// A TCP server broadcasting every slice it is given to all connected clients.
+///
+/// Clients are accepted on a background thread, and each is written to from its own thread, so
+/// that a slow client can't hold up the source or the other clients. A client which falls too far
+/// behind is disconnected.
+#[derive(Debug)]
+pub struct Server {
+    local_addr: SocketAddr,
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+#[derive(Debug)]
+struct Client {
+    peer_addr: SocketAddr,
+    sender: SyncSender<Slice>,
+}
+
+impl Server {
+    /// Listen on `addr`, accepting clients which will receive data in `format`.
+    ///
+    /// `source` describes where the data comes from, for the metadata of `Format::Capture`.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        format: Format,
+        source: String,
+    ) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = clients.clone();
+        std::thread::Builder::new()
+            .name("serve".into())
+            .spawn(move || accept(listener, format, source, accepted))?;
+
+        Ok(Self {
+            local_addr,
+            clients,
+        })
+    }
+
+    /// The local address on which clients are accepted.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The number of currently connected clients.
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Send `bytes`, received at `timestamp`, to every connected client.
+    pub fn broadcast(&self, bytes: &[u8], timestamp: SystemTime) {
+        let slice: Slice = (bytes.into(), timestamp);
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|client| match client.sender.try_send(slice.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    log::warn!("client {} is too slow, disconnecting", client.peer_addr);
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+fn accept(listener: TcpListener, format: Format, source: String, clients: Arc<Mutex<Vec<Client>>>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("error accepting client: {}", e);
+                continue;
+            }
+        };
+        let Ok(peer_addr) = stream.peer_addr() else {
+            continue;
+        };
+
+        let (sender, receiver) = mpsc::sync_channel(CLIENT_QUEUE_LENGTH);
+        let source = source.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("serve {}", peer_addr))
+            .spawn(move || {
+                if let Err(e) = serve(stream, format, &source, receiver) {
+                    log::info!("client {} disconnected: {}", peer_addr, e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("error starting client {}: {}", peer_addr, e);
+            continue;
+        }
+
+        log::info!("client {} connected", peer_addr);
+        clients.lock().unwrap().push(Client { peer_addr, sender });
+    }
+}
+
+/// Write slices to one client until either side goes away.
+fn serve(
+    stream: TcpStream,
+    format: Format,
+    source: &str,
+    receiver: Receiver<Slice>,
+) -> Result<(), std::io::Error> {
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    match format {
+        Format::Raw => {
+            let mut stream = stream;
+            for (bytes, _) in receiver {
+                stream.write_all(&bytes)?;
+            }
+        }
+        Format::Capture => {
+            // Send the header straight away, so that clients can start reading it
+            let mut writer = capture::Writer::new(stream, &capture::Metadata::new(source))?;
+            writer.flush()?;
+            for (bytes, timestamp) in receiver {
+                writer.write(&bytes, timestamp)?;
+                writer.flush()?;
+            }
+            writer.finish()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Instant;
+
+    fn connect(server: &Server) -> TcpStream {
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+
+        // Wait for the server to accept it
+        let start = Instant::now();
+        let count = server.client_count();
+        while server.client_count() == count {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        stream
+    }
+
+    #[test]
+    fn raw() {
+        let server = Server::bind("127.0.0.1:0", Format::Raw, "test".into()).unwrap();
+        let mut a = connect(&server);
+        let mut b = connect(&server);
+
+        server.broadcast(b"\x00\xff\x7e\x07", SystemTime::now());
+        server.broadcast(b"\x7e\x08", SystemTime::now());
+
+        for client in [&mut a, &mut b] {
+            let mut buffer = [0u8; 6];
+            client.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, b"\x00\xff\x7e\x07\x7e\x08");
+        }
+
+        // Disconnected clients are forgotten
+        drop(a);
+        drop(b);
+        let start = Instant::now();
+        while server.client_count() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            server.broadcast(b"\x00", SystemTime::now());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn capture() {
+        let server = Server::bind("127.0.0.1:0", Format::Capture, "test".into()).unwrap();
+        let client = connect(&server);
+
+        let t = SystemTime::UNIX_EPOCH + Duration::from_millis(1723500000123);
+        server.broadcast(b"\x00\xff\x7e\x07", t);
+        server.broadcast(b"\x7e\x08", t + Duration::from_secs(1));
+
+        // Records can be read as they arrive, without waiting for the stream to end
+        let mut reader = capture::Reader::new(client).unwrap();
+        assert_eq!(reader.metadata().unwrap().source, "test");
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(
+            (record.data.as_slice(), record.timestamp),
+            (&b"\x00\xff\x7e\x07"[..], t)
+        );
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(
+            (record.data.as_slice(), record.timestamp),
+            (&b"\x7e\x08"[..], t + Duration::from_secs(1))
+        );
+    }
+}
@@ -1,4 +1,4 @@
-pub const ENUMERATION_SEQUENCE: &'static [u8] = &[
+pub const ENUMERATION_SEQUENCE: &[u8] = &[
     0x00, 0xFF, 0xFF, 0x7E, 0x07, 0x12, 0x01, 0x0B, 0x00, 0x01, 0xFE, 0x83, 0x7E, 0x08, 0xFF, 0x7E,
     0x07, 0x92, 0x01, 0x0B, 0x01, 0x01, 0x73, 0x10, 0x7E, 0x08, 0x00, 0xFF, 0xFF, 0x7E, 0x07, 0x00,
     0x00, 0x00, 0x14, 0x37, 0x7E, 0x01, 0x92, 0x66, 0x12, 0x35, 0x06, 0x1A, 0x7E, 0x08, 0xFF, 0x7E,